@@ -0,0 +1,63 @@
+use export::RunSetup;
+
+const SCENARIOS: &[usize] = &[0, 2];
+
+// **********
+// BENCH PLAN
+// **********
+/// The configurations measured by a bench : every power of the number of Persons with every scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchPlan {
+    pub pow_pers: Vec<usize>,
+    pub scenarios: Vec<usize>,
+}
+
+impl BenchPlan {
+
+    /// Parses the powers and the scenarios, written as lists of values and ranges (see `parse_list`).
+    /// Only the scenarios 0 and 2 are implemented.
+    pub fn new(pow_pers: &str, scenarios: &str) -> Result<BenchPlan, String> {
+        let plan = BenchPlan { pow_pers: parse_list(pow_pers)?, scenarios: parse_list(scenarios)? };
+        if let Some(scenario) = plan.scenarios.iter().find(|s| !SCENARIOS.contains(s)) {
+            return Err(format!("\"{}\" : the scenario {} is not implemented, expected 0 or 2", scenarios, scenario));
+        }
+        Ok(plan)
+    }
+
+    /// The configurations of the bench in the order they run (scenario by scenario), with their power,
+    /// the ones already in `done` left out.
+    pub fn pending(&self, xsize: usize, ysize: usize, seed: usize, config: u64, done: &[RunSetup]) -> Vec<(usize, RunSetup)> {
+        let mut setups = Vec::new();
+        for &scenario in &self.scenarios {
+            for &pow in &self.pow_pers {
+                let setup = RunSetup { scenario, persons: (2_usize).pow(pow as u32), xsize, ysize, seed, config };
+                if !done.contains(&setup) {
+                    setups.push((pow, setup));
+                }
+            }
+        }
+        setups
+    }
+}
+
+/// Parses a comma separated list of values and of ranges `a..b` (both ends included),
+/// such as `2..6,8,10`.
+pub fn parse_list(s: &str) -> Result<Vec<usize>, String> {
+    let value = |v: &str| v.trim().parse::<usize>().map_err(|e| format!("invalid value in \"{}\" : {}", s, e));
+    let mut values = Vec::new();
+    for item in s.split(',') {
+        let bounds: Vec<&str> = item.split("..").collect();
+        match bounds.len() {
+            1 => values.push(value(bounds[0])?),
+            2 => {
+                let (from, to) = (value(bounds[0])?, value(bounds[1])?);
+                if from > to {
+                    return Err(format!("\"{}\" : the range {} is empty", s, item));
+                }
+                values.extend(from..to + 1);
+            }
+            _ => return Err(format!("\"{}\" is not a list of values and ranges a..b", s)),
+        }
+    }
+    Ok(values)
+}
@@ -0,0 +1,212 @@
+use export::AggregateRow;
+
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+const WIDTH: f64 = 800.;
+const HEIGHT: f64 = 500.;
+// room for the axes labels on the left and at the bottom, for the legend on the right
+const LEFT: f64 = 90.;
+const RIGHT: f64 = 150.;
+const TOP: f64 = 40.;
+const BOTTOM: f64 = 60.;
+const COLORS: &[&str] = &["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b"];
+
+// ******
+// SERIES
+// ******
+/// A line of a chart : its points and their error (the half height of their error bar).
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub label: String,
+    pub points: Vec<(f64, f64, f64)>,
+}
+
+// ****
+// AXIS
+// ****
+#[derive(Debug, Copy, Clone)]
+struct Axis {
+    min: f64,
+    max: f64,
+    log: bool,
+}
+
+impl Axis {
+
+    // an axis holding the values, starting at 0 when linear
+    fn new(values: &[f64], log: bool) -> Axis {
+        let positive: Vec<f64> = values.iter().cloned().filter(|v| !log || *v > 0.).collect();
+        let mut min = positive.iter().cloned().fold(f64::INFINITY, f64::min);
+        let mut max = positive.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        if positive.is_empty() {
+            min = 1.;
+            max = 10.;
+        }
+        if log {
+            min = 10_f64.powf(min.log10().floor());
+            max = 10_f64.powf(max.log10().ceil());
+            if max <= min {
+                max = min * 10.;
+            }
+        } else {
+            min = min.min(0.);
+            if max <= min {
+                max = min + 1.;
+            }
+        }
+        Axis { min, max, log }
+    }
+
+    // position of a value between 0 and 1
+    fn ratio(&self, v: f64) -> f64 {
+        if self.log {
+            (v.max(self.min).log10() - self.min.log10()) / (self.max.log10() - self.min.log10())
+        } else {
+            (v - self.min) / (self.max - self.min)
+        }
+    }
+
+    // decades for a log axis (with their multiples by 2 and 5 if there are few of them),
+    // about 5 round values for a linear one
+    fn ticks(&self) -> Vec<f64> {
+        let mut ticks = Vec::new();
+        if self.log {
+            let decades = (self.max / self.min).log10().round() as i32;
+            let mut decade = self.min;
+            while decade <= self.max * 1.0001 {
+                for m in if decades <= 2 { &[1., 2., 5.][..] } else { &[1.][..] } {
+                    if decade * m <= self.max * 1.0001 {
+                        ticks.push(decade * m);
+                    }
+                }
+                decade *= 10.;
+            }
+        } else {
+            let raw = (self.max - self.min) / 5.;
+            let magnitude = 10_f64.powf(raw.log10().floor());
+            let step = [1., 2., 5., 10.].iter().map(|m| m * magnitude).find(|s| *s >= raw).unwrap_or(raw);
+            let mut tick = (self.min / step).ceil() * step;
+            while tick <= self.max + step * 1e-6 {
+                ticks.push(tick);
+                tick += step;
+            }
+        }
+        ticks
+    }
+}
+
+fn label_of(v: f64) -> String {
+    if v == 0. {
+        "0".to_string()
+    } else if v.abs() >= 1e5 || v.abs() < 1e-3 {
+        format!("{:e}", v)
+    } else {
+        let s = format!("{:.4}", v);
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    }
+}
+
+// *****
+// CHART
+// *****
+/// A line chart, drawn as an SVG document.
+#[derive(Debug, Clone)]
+pub struct Chart {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub log_x: bool,
+    pub log_y: bool,
+    pub error_bars: bool,
+    pub series: Vec<Series>,
+}
+
+impl Chart {
+
+    pub fn to_svg(&self) -> String {
+        let points: Vec<&(f64, f64, f64)> = self.series.iter().flat_map(|s| s.points.iter()).collect();
+        let err = |p: &(f64, f64, f64)| if self.error_bars { p.2 } else { 0. };
+        let xs: Vec<f64> = points.iter().map(|p| p.0).collect();
+        let ys: Vec<f64> = points.iter().flat_map(|p| vec![p.1 - err(p), p.1 + err(p)]).collect();
+        let (x_axis, y_axis) = (Axis::new(&xs, self.log_x), Axis::new(&ys, self.log_y));
+        let (plot_w, plot_h) = (WIDTH - LEFT - RIGHT, HEIGHT - TOP - BOTTOM);
+        let px = |x: f64| LEFT + x_axis.ratio(x) * plot_w;
+        let py = |y: f64| TOP + (1. - y_axis.ratio(y)) * plot_h;
+
+        let mut svg = String::new();
+        // writing to a String does not fail
+        let _ = writeln!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\" font-size=\"12\">", WIDTH, HEIGHT);
+        let _ = writeln!(svg, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>");
+        let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"16\">{}</text>", LEFT + plot_w / 2., TOP / 2. + 6., self.title);
+
+        // grid and ticks
+        for x in x_axis.ticks() {
+            let _ = writeln!(svg, "<line x1=\"{0:.1}\" y1=\"{1}\" x2=\"{0:.1}\" y2=\"{2}\" stroke=\"#ddd\"/>", px(x), TOP, TOP + plot_h);
+            let _ = writeln!(svg, "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>", px(x), TOP + plot_h + 18., label_of(x));
+        }
+        for y in y_axis.ticks() {
+            let _ = writeln!(svg, "<line x1=\"{1}\" y1=\"{0:.1}\" x2=\"{2}\" y2=\"{0:.1}\" stroke=\"#ddd\"/>", py(y), LEFT, LEFT + plot_w);
+            let _ = writeln!(svg, "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>", LEFT - 6., py(y) + 4., label_of(y));
+        }
+        let _ = writeln!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"black\"/>", LEFT, TOP, plot_w, plot_h);
+        let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}{}</text>", LEFT + plot_w / 2., HEIGHT - 15.,
+                         self.x_label, if self.log_x { " (log)" } else { "" });
+        let _ = writeln!(svg, "<text transform=\"translate(20,{}) rotate(-90)\" text-anchor=\"middle\">{}{}</text>", TOP + plot_h / 2.,
+                         self.y_label, if self.log_y { " (log)" } else { "" });
+
+        // lines, points, error bars and legend
+        for (idx, series) in self.series.iter().enumerate() {
+            let color = COLORS[idx % COLORS.len()];
+            let path: Vec<String> = series.points.iter().map(|p| format!("{:.1},{:.1}", px(p.0), py(p.1))).collect();
+            let _ = writeln!(svg, "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>", path.join(" "), color);
+            for p in &series.points {
+                let _ = writeln!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"{}\"/>", px(p.0), py(p.1), color);
+                if err(p) > 0. {
+                    let (top, bottom) = (py(p.1 + p.2), py(p.1 - p.2));
+                    let _ = writeln!(svg, "<path d=\"M{0:.1},{1:.1}V{2:.1}M{3:.1},{1:.1}H{4:.1}M{3:.1},{2:.1}H{4:.1}\" stroke=\"{5}\"/>",
+                                     px(p.0), top, bottom, px(p.0) - 4., px(p.0) + 4., color);
+                }
+            }
+            let ly = TOP + 10. + idx as f64 * 20.;
+            let _ = writeln!(svg, "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"2\"/>", WIDTH - RIGHT + 15., ly, WIDTH - RIGHT + 40., ly, color);
+            let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\">{}</text>", WIDTH - RIGHT + 46., ly + 4., series.label);
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        File::create(path)?.write_all(self.to_svg().as_bytes())
+    }
+}
+
+// file name, title, y axis label and measure (with its standard deviation) of a bench chart
+type Metric = (&'static str, &'static str, &'static str, fn(&AggregateRow) -> (f64, f64));
+
+/// The charts of a bench, by file name : wall, user and system time and max RSS
+/// against the number of Persons, one line per scenario.
+pub fn bench_charts(aggregates: &[AggregateRow], log_x: bool, log_y: bool, error_bars: bool) -> Vec<(String, Chart)> {
+    let mut scenarios: Vec<usize> = aggregates.iter().map(|a| a.setup.scenario).collect();
+    scenarios.sort();
+    scenarios.dedup();
+    let metrics: [Metric; 4] = [
+        ("chart_time.svg", "Wall time", "time (s)", |a| (a.time, a.time_stddev)),
+        ("chart_utime.svg", "User CPU time", "user time (s)", |a| (a.utime, a.utime_stddev)),
+        ("chart_stime.svg", "System CPU time", "system time (s)", |a| (a.stime, a.stime_stddev)),
+        ("chart_mem.svg", "Max RSS", "max RSS (kB)", |a| (a.maxrss / 1024., 0.)), // as displayed by PerfResult
+    ];
+    metrics.iter().map(|&(file, title, y_label, value)| {
+        let series = scenarios.iter().map(|&scenario| {
+            let mut points: Vec<(f64, f64, f64)> = aggregates.iter().filter(|a| a.setup.scenario == scenario)
+                .map(|a| { let (v, e) = value(a); (a.setup.persons as f64, v, e) }).collect();
+            points.sort_by(|p, q| p.0.partial_cmp(&q.0).unwrap());
+            Series { label: format!("scenario {}", scenario), points }
+        }).collect();
+        (file.to_string(), Chart { title: title.to_string(), x_label: "Persons".to_string(), y_label: y_label.to_string(),
+            log_x, log_y, error_bars, series })
+    }).collect()
+}
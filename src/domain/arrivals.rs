@@ -0,0 +1,145 @@
+extern crate rand;
+
+use super::{Point, configure_person};
+use super::building::Building;
+use super::config::SimConfig;
+use super::person::Person;
+use super::population::class_of;
+
+use std::fmt; // formatting for console display
+use std::str::FromStr;
+use self::rand::{Rng, SeedableRng, StdRng};
+
+// ******
+// SOURCE
+// ******
+/// A zone of the ground floor where new Persons arrive during the simulation,
+/// `rate` Persons per tick (on average) from the tick `start` until the tick `stop`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Source {
+    pub lower_left: Point,
+    pub upper_right: Point,
+    pub rate: f32,
+    pub start: u64,
+    pub stop: Option<u64>,
+}
+
+impl Source {
+
+    /// Number of Persons arriving during a tick : the fractional parts of the rate
+    /// add up from tick to tick, so that 0.25 makes a Person every 4 ticks.
+    pub fn due(&self, tick: u64) -> usize {
+        if tick < self.start || self.stop.is_some_and(|stop| tick >= stop) {
+            return 0;
+        }
+        let elapsed = (tick - self.start) as f64;
+        let rate = self.rate as f64;
+        ((elapsed + 1.) * rate).floor() as usize - (elapsed * rate).floor() as usize
+    }
+}
+
+/// Parses a source written as `x,y:x,y:rate` (the lower left and upper right corners of the zone)
+/// optionally followed by `:start` and `:stop` ticks
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Source, String> {
+        let fields: Vec<&str> = s.split(':').collect();
+        if fields.len() < 3 || fields.len() > 5 {
+            return Err(format!("\"{}\" is not a source, expected x,y:x,y:rate[:start[:stop]]", s));
+        }
+        let tick = |f: &str| f.trim().parse::<u64>().map_err(|e| format!("invalid tick in \"{}\" : {}", s, e));
+        let source = Source {
+            lower_left: fields[0].parse::<Point>()?,
+            upper_right: fields[1].parse::<Point>()?,
+            rate: fields[2].trim().parse::<f32>().map_err(|e| format!("invalid rate in \"{}\" : {}", s, e))?,
+            start: match fields.get(3) { Some(f) => tick(f)?, None => 0 },
+            stop: match fields.get(4) { Some(f) => Some(tick(f)?), None => None },
+        };
+        if source.rate < 0. {
+            return Err(format!("\"{}\" : the rate must not be negative", s));
+        }
+        Ok(source)
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Source : {{ zone : {} - {}, rate : {}, start : {}, stop : {:?} }}",
+               self.lower_left, self.upper_right, self.rate, self.start, self.stop)
+    }
+}
+
+// ********
+// ARRIVALS
+// ********
+/// Creates the Persons arriving from the sources of a simulation, tick after tick.
+/// Persons that find their source zone full wait for a free cell.
+#[derive(Debug)]
+pub struct Arrivals {
+    sources: Vec<Source>,
+    waiting: Vec<usize>, // per source : Persons due that could not be placed yet
+    next_rank: usize, // rank of the next Person : the initial population comes first
+    next_tick: u64, // first tick not handled yet
+    rng: StdRng,
+    /// Persons placed on the Terrain so far
+    pub arrived: usize,
+}
+
+impl Arrivals {
+
+    /// Arrivals from the sources of the configuration, after `first_rank` Persons already placed.
+    pub fn new(config: &SimConfig, first_rank: usize) -> Arrivals {
+        let seed: &[_] = &[config.seed + 1,]; // seed of the simulation to get the same arrivals between executions
+        Arrivals {
+            sources: config.sources.clone(),
+            waiting: vec![0; config.sources.len()],
+            next_rank: first_rank,
+            next_tick: 0,
+            rng: StdRng::from_seed(seed),
+            arrived: 0,
+        }
+    }
+
+    /// Persons due since the last call, up to the current tick of the ground floor,
+    /// already placed on a free cell of their source.
+    pub fn spawn(&mut self, config: &SimConfig, building: &Building) -> Vec<Person> {
+        let pterrain = building.get_floor(0);
+        let mut terrain = pterrain.lock().unwrap();
+        let tick = terrain.get_tick();
+        for t in self.next_tick..tick + 1 {
+            for (idx, source) in self.sources.iter().enumerate() {
+                self.waiting[idx] += source.due(t);
+            }
+        }
+        self.next_tick = tick + 1;
+
+        let mut persons = Vec::new();
+        for (idx, source) in self.sources.iter().enumerate() {
+            while self.waiting[idx] > 0 {
+                let class = class_of(self.next_rank, &config.population);
+                let size = config.population[class].footprint as usize;
+                let free = terrain.get_free_areas_in(&source.lower_left, &source.upper_right, size);
+                let pt = match self.rng.choose(&free) {
+                    Some(pt) => *pt,
+                    None => break, // the zone is full
+                };
+                let mut new_pers = Person::new((self.next_rank + 1) * 10, pt);
+                config.population[class].apply(class, &mut new_pers);
+                configure_person(config, &mut new_pers);
+                new_pers.place_on_terrain(&mut terrain);
+                debug!("tick {} : arrival of {}", tick, &new_pers);
+                persons.push(new_pers);
+                self.waiting[idx] -= 1;
+                self.next_rank += 1;
+                self.arrived += 1;
+            }
+        }
+        persons
+    }
+
+    /// Persons due that could not be placed yet (their source zone is full).
+    pub fn get_waiting_cnt(&self) -> usize {
+        self.waiting.iter().sum()
+    }
+}
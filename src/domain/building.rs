@@ -0,0 +1,235 @@
+use super::Point;
+use super::terrain::Terrain;
+use super::person::{Person, Lifecycle};
+
+use std::collections::VecDeque;
+use lock::{InstrumentedMutex, LockStats};
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// ********
+// BUILDING
+// ********
+/// A staircase going down from an upper floor to a lower one.
+#[derive(Debug, Copy, Clone)]
+pub struct Stair {
+    pub upper_floor: usize,
+    /// cell of the upper floor leading to the stair (an exit point of this floor)
+    pub upper_point: Point,
+    pub lower_floor: usize,
+    /// cell of the lower floor where Persons arrive
+    pub lower_point: Point,
+    /// turns needed to walk down the stair
+    pub delay: u32,
+    /// number of Persons walking down the stair at the same time, the others wait at the top
+    pub capacity: usize,
+}
+
+/// Several Terrain floors linked by stairs, the floor 0 being the ground floor :
+/// Persons escape only through its exits.
+/// Each floor is protected by its own lock so that floors can be handled by different threads,
+/// the locks record how long the threads wait for them.
+/// The floors share a clock : the Persons are timed with it whatever floor they are on.
+pub struct Building {
+    floors: Vec<Arc<InstrumentedMutex<Terrain>>>,
+    stairs: Vec<Stair>,
+    queues: Vec<Mutex<VecDeque<(usize, u32)>>>, // per stair : Person ids and remaining turns
+    ground_tick: AtomicUsize, // copy of the tick of the ground floor, read without its lock
+    tick_lock: Mutex<()>,
+    tick_changed: Condvar, // the ground floor went to its next tick
+}
+
+impl Building {
+
+    /// Builds a Building from its floors (ground floor first) and stairs.
+    /// Upper floors can only be left by their stairs, their Persons head to their first stair.
+    pub fn new(mut floors: Vec<Terrain>, stairs: Vec<Stair>) -> Building {
+        let clock = Arc::new(AtomicUsize::new(0));
+        for floor in floors.iter_mut() {
+            floor.share_clock(clock.clone());
+        }
+        for (idx, floor) in floors.iter_mut().enumerate().skip(1) {
+            floor.clear_exit_points();
+            floor.set_stair_exits();
+            for stair in stairs.iter().filter(|s| s.upper_floor == idx) {
+                floor.add_exit_point(stair.upper_point);
+            }
+            if let Some(stair) = stairs.iter().find(|s| s.upper_floor == idx) {
+                floor.set_azimuth(stair.upper_point);
+            }
+        }
+
+        let queues = stairs.iter().map(|_| Mutex::new(VecDeque::new())).collect();
+        Building {
+            floors: floors.into_iter().map(|f| Arc::new(InstrumentedMutex::new(f))).collect(),
+            stairs,
+            queues,
+            ground_tick: AtomicUsize::new(0),
+            tick_lock: Mutex::new(()),
+            tick_changed: Condvar::new(),
+        }
+    }
+
+    /// A single floor Building : the Terrain alone.
+    pub fn new_single_floor(terrain: Terrain) -> Building {
+        Building::new(vec![terrain], Vec::new())
+    }
+
+    /// Stacks floors linked by stairs in the upper right corner.
+    pub fn new_stacked(floors: Vec<Terrain>, delay: u32, capacity: usize) -> Building {
+        let mut stairs: Vec<Stair> = Vec::with_capacity(floors.len());
+        for (idx, floor) in floors.iter().enumerate().skip(1) {
+            let (x, y) = (floor.xsize as isize, floor.ysize as isize);
+            stairs.push(Stair {
+                upper_floor: idx,
+                upper_point: Point{x: x - 2, y: y - 2},
+                lower_floor: idx - 1,
+                lower_point: Point{x: x - 4, y: y - 3},
+                delay,
+                capacity,
+            });
+        }
+        Building::new(floors, stairs)
+    }
+
+    pub fn get_floors(&self) -> &[Arc<InstrumentedMutex<Terrain>>] {
+        &self.floors
+    }
+
+    pub fn get_floor(&self, floor: usize) -> Arc<InstrumentedMutex<Terrain>> {
+        self.floors[floor].clone()
+    }
+
+    /// Stats of the locks of the floors, added up.
+    pub fn get_lock_stats(&self) -> LockStats {
+        let mut stats = LockStats::default();
+        for floor in &self.floors {
+            stats.add(&floor.stats());
+        }
+        stats
+    }
+
+    pub fn reset_lock_stats(&self) {
+        for floor in &self.floors {
+            floor.reset_stats();
+        }
+    }
+
+    pub fn get_stairs(&self) -> &[Stair] {
+        &self.stairs
+    }
+
+    /// Persons that escaped by the exits of the ground floor.
+    pub fn get_exited_cnt(&self) -> usize {
+        self.floors[0].lock().unwrap().get_exited_cnt()
+    }
+
+    pub fn get_casualties_cnt(&self) -> usize {
+        self.floors.iter().map(|f| f.lock().unwrap().get_casualties_cnt()).sum()
+    }
+
+    /// Tick of the ground floor, as of the last turn played by the Building
+    /// (read without taking the lock of the floor).
+    pub fn get_tick(&self) -> u64 {
+        self.ground_tick.load(Ordering::Relaxed) as u64
+    }
+
+    /// Waits until the ground floor gets past the tick, or until the timeout.
+    pub fn wait_past_tick(&self, tick: u64, timeout: Duration) {
+        let guard = self.tick_lock.lock().unwrap();
+        let _ = self.tick_changed.wait_timeout_while(guard, timeout, |_| self.get_tick() <= tick).unwrap();
+    }
+
+    // copies the tick of the ground floor, and wakes up the threads waiting for it to change
+    fn set_ground_tick(&self, tick: u64) {
+        if self.ground_tick.swap(tick as usize, Ordering::Relaxed) != tick as usize {
+            let _guard = self.tick_lock.lock().unwrap();
+            self.tick_changed.notify_all();
+        }
+    }
+
+    /// Moves the floors without any Person on them to their next tick
+    /// (their tick only advances with the turns of their Persons).
+    /// Returns whether the ground floor was one of them.
+    pub fn advance_idle_floors(&self) -> bool {
+        let mut ground_idle = false;
+        for (floor, pterrain) in self.floors.iter().enumerate() {
+            let mut terrain = pterrain.lock().unwrap();
+            let idle = terrain.get_active_cnt() == 0;
+            if idle {
+                terrain.advance_tick();
+            }
+            if floor == 0 {
+                ground_idle = idle;
+                self.set_ground_tick(terrain.get_tick());
+            }
+        }
+        ground_idle
+    }
+
+    /// Persons that left the Building : escaped or casualties.
+    pub fn get_left_cnt(&self) -> usize {
+        self.get_exited_cnt() + self.get_casualties_cnt()
+    }
+
+    /// Plays one turn of a Person : a move on its floor or a step in its staircase.
+    /// Only the lock of the floor the Person is on is taken.
+    pub fn look_and_move(&self, pers: &mut Person) {
+        if let Some(stair) = pers.stair {
+            self.walk_stair(pers, stair);
+            return;
+        }
+
+        let floor = pers.floor;
+        {
+            let mut terrain = self.floors[floor].lock().unwrap();
+            pers.look_and_move(&mut terrain);
+            if floor == 0 {
+                self.set_ground_tick(terrain.get_tick());
+            }
+        }
+
+        if pers.get_state() == Lifecycle::InStair {
+            // the Person left its floor by a stair : it queues to walk down
+            let stair = self.stairs.iter()
+                .position(|s| s.upper_floor == floor && pers.covers(&s.upper_point))
+                .expect("Upper floors can only be left by a stair");
+            trace!("I, {} take the stair {} down to floor {}", pers.id, stair, self.stairs[stair].lower_floor);
+            self.queues[stair].lock().unwrap().push_back((pers.id, self.stairs[stair].delay));
+            pers.stair = Some(stair);
+        }
+    }
+
+    // one turn in a staircase : walk down if among the first Persons, then try to arrive
+    fn walk_stair(&self, pers: &mut Person, stair_idx: usize) {
+        let stair = self.stairs[stair_idx];
+        let arrived = {
+            let mut queue = self.queues[stair_idx].lock().unwrap();
+            let rank = queue.iter().position(|&(id, _)| id == pers.id)
+                .expect("A Person in a stair must be in its queue");
+            if rank < stair.capacity {
+                let walker = &mut queue[rank];
+                walker.1 = walker.1.saturating_sub(1);
+                walker.1 == 0
+            } else {
+                false // too many Persons in the stair : wait at the top
+            }
+        };
+
+        if arrived {
+            let mut terrain = self.floors[stair.lower_floor].lock().unwrap();
+            if terrain.check_valid_footprint(&stair.lower_point, pers.footprint as usize, 0) {
+                pers.position = stair.lower_point;
+                pers.floor = stair.lower_floor;
+                pers.stair = None;
+                pers.target_exit.set(None); // exits of the new floor
+                pers.forget();
+                pers.place_on_terrain(&mut terrain);
+                self.queues[stair_idx].lock().unwrap().retain(|&(id, _)| id != pers.id);
+                trace!("I, {} arrived on floor {}", pers.id, pers.floor);
+            } // else : the landing is occupied, try again at next turn
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use super::{Point, XSIZE, YSIZE};
+use super::hazard::HazardRules;
+use super::strategy::StrategyKind;
+use super::exit::ExitChoice;
+use super::population::AgentClass;
+use super::person::DEFAULT_MEMORY;
+use super::perception::Sign;
+use super::arrivals::Source;
+use super::terrain::DEFAULT_SEED;
+
+use std::path::PathBuf;
+
+// **********
+// SIM CONFIG
+// **********
+/// Simulation options that do not depend on the selected scenario
+/// (they describe the Terrain and the Persons, not the threading).
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    /// size of the Terrain (of each floor)
+    pub xsize: usize,
+    pub ysize: usize,
+    /// cost map applied to the Terrain (see `Terrain::load_cost_map`)
+    pub cost_map: Option<PathBuf>,
+    /// layout events (doors, barriers) scheduled on the Terrain (see `Terrain::load_timeline`)
+    pub timeline: Option<PathBuf>,
+    /// exit points added to the default exit of the Terrain
+    pub exits: Vec<Point>,
+    /// points where a hazard starts at the beginning of the simulation
+    pub hazards: Vec<Point>,
+    pub hazard_rules: HazardRules,
+    /// how much the Persons avoid hazards (0 : they ignore them)
+    pub hazard_aversion: f32,
+    /// the way the Persons choose their moves
+    pub strategy: StrategyKind,
+    /// how the Persons pick the exit they head to
+    pub exit_choice: ExitChoice,
+    /// turns a Person must save to leave its exit for a less congested one
+    pub exit_switch_margin: f32,
+    /// classes of Persons and their shares of the population
+    pub population: Vec<AgentClass>,
+    /// number of recently left cells the Persons avoid going back to (0 : no memory and no random ties)
+    pub memory: usize,
+    /// face to face Persons that want each other's cell exchange them
+    pub swap: bool,
+    /// distance up to which the Persons see (0 : they know the whole floor)
+    pub perception: f32,
+    /// signage cells showing the way to the Persons with a limited perception
+    pub signs: Vec<Sign>,
+    /// number of Persons per group (1 : no groups)
+    pub group_size: usize,
+    /// maximum distance between the members of a group
+    pub group_distance: f32,
+    /// zones of the ground floor where new Persons arrive during the simulation
+    pub sources: Vec<Source>,
+    /// if set, the simulation runs for this number of ticks (of the ground floor)
+    /// instead of stopping once every Person left
+    pub duration: Option<u64>,
+    /// ticks left out of the steady state averages, None : detected from the occupancy
+    pub warmup: Option<u64>,
+    /// number of floors of the Building, the ground floor included
+    pub floors: usize,
+    /// turns needed to walk down a stair between two floors
+    pub stair_delay: u32,
+    /// number of Persons walking down a stair at the same time
+    pub stair_capacity: usize,
+    /// seed of the random placements, hazard spreading and arrivals
+    pub seed: usize,
+}
+
+impl Default for SimConfig {
+    fn default() -> SimConfig {
+        SimConfig {
+            xsize: XSIZE,
+            ysize: YSIZE,
+            cost_map: None,
+            timeline: None,
+            exits: Vec::new(),
+            hazards: Vec::new(),
+            hazard_rules: HazardRules::default(),
+            hazard_aversion: 0.,
+            strategy: StrategyKind::default(),
+            exit_choice: ExitChoice::default(),
+            exit_switch_margin: 10.,
+            population: vec![AgentClass::walker()],
+            memory: DEFAULT_MEMORY,
+            swap: false,
+            perception: 0.,
+            signs: Vec::new(),
+            group_size: 1,
+            group_distance: 3.,
+            sources: Vec::new(),
+            duration: None,
+            warmup: None,
+            floors: 1,
+            stair_delay: 10,
+            stair_capacity: 4,
+            seed: DEFAULT_SEED,
+        }
+    }
+}
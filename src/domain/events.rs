@@ -0,0 +1,70 @@
+use super::Point;
+
+use std::fmt; // formatting for console display
+use std::io;
+
+// ******
+// EVENTS
+// ******
+/// A change of the Terrain layout.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LayoutEvent {
+    /// cells of the rectangle (lower left, upper right) become obstacles : a door closes, a barrier appears
+    Block(Point, Point),
+    /// cells of the rectangle (lower left, upper right) become free : a door opens, a barrier is removed
+    Clear(Point, Point),
+}
+
+/// A layout change planned for a given tick of the simulation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScheduledEvent {
+    pub tick: u64,
+    pub event: LayoutEvent,
+}
+
+/// Parses a timeline : one event per line, written as
+/// `<tick> <close|block|open|clear> <x1> <y1> <x2> <y2>`
+/// where (x1, y1) is the lower left and (x2, y2) the upper right corner of the rectangle.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_timeline(timeline: &str) -> io::Result<Vec<ScheduledEvent>> {
+    let mut events: Vec<ScheduledEvent> = Vec::new();
+
+    for (line_nb, line) in timeline.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData,
+                                                   format!("timeline line {} : {} in \"{}\"", line_nb + 1, what, line));
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 6 {
+            return Err(invalid("expected 6 fields"));
+        }
+
+        let tick = fields[0].parse::<u64>().map_err(|_| invalid("invalid tick"))?;
+        let mut coords = [0_isize; 4];
+        for (coord, field) in coords.iter_mut().zip(&fields[2..]) {
+            *coord = field.parse::<isize>().map_err(|_| invalid("invalid coordinate"))?;
+        }
+        let lower_left = Point{x: coords[0], y: coords[1]};
+        let upper_right = Point{x: coords[2], y: coords[3]};
+
+        let event = match fields[1] {
+            "close" | "block" => LayoutEvent::Block(lower_left, upper_right),
+            "open" | "clear" => LayoutEvent::Clear(lower_left, upper_right),
+            _ => return Err(invalid("unknown event")),
+        };
+        events.push(ScheduledEvent{tick, event});
+    }
+    Ok(events)
+}
+
+impl fmt::Display for LayoutEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LayoutEvent::Block(ref ll, ref ur) => write!(f, "block {} {}", ll, ur),
+            LayoutEvent::Clear(ref ll, ref ur) => write!(f, "clear {} {}", ll, ur),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use super::Point;
+use super::terrain::Terrain;
+use super::person::Person;
+
+use std::f32;
+use std::fmt; // formatting for console display
+use std::str::FromStr;
+
+/// Persons standing within this distance of an exit are counted in its crowd.
+pub const CROWD_RADIUS: isize = 6;
+
+/// Ticks between two reviews of its exit by a Person.
+pub const EXIT_REVIEW_TICKS: u64 = 10;
+
+// ****
+// EXIT
+// ****
+/// Adjacent exit points of a Terrain, used as a single exit (a door, a corridor...).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exit {
+    pub id: usize,
+    pub cells: Vec<Point>,
+}
+
+impl Exit {
+
+    /// Groups exit points into exits : points next to each other belong to the same exit.
+    /// Exits are numbered in the order of their first point.
+    pub fn group(points: &[Point]) -> Vec<Exit> {
+        let mut exits: Vec<Exit> = Vec::new();
+        for point in points {
+            let touching: Vec<usize> = exits.iter()
+                .filter(|e| e.cells.iter().any(|c| (c.x - point.x).abs() <= 1 && (c.y - point.y).abs() <= 1))
+                .map(|e| e.id)
+                .collect();
+            match touching.first() {
+                None => {
+                    let id = exits.len();
+                    exits.push(Exit{id, cells: vec![*point]});
+                }
+                Some(&first) => {
+                    // the point may join several exits into one
+                    for &other in touching.iter().skip(1).rev() {
+                        let cells = exits.remove(other).cells;
+                        exits[first].cells.extend(cells);
+                    }
+                    exits[first].cells.push(*point);
+                }
+            }
+            for (id, exit) in exits.iter_mut().enumerate() {
+                exit.id = id;
+            }
+        }
+        exits
+    }
+
+    /// Number of Persons that can leave by this exit at the same time.
+    pub fn width(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn center(&self) -> Point {
+        let n = self.cells.len() as isize;
+        Point {
+            x: self.cells.iter().map(|c| c.x).sum::<isize>() / n,
+            y: self.cells.iter().map(|c| c.y).sum::<isize>() / n,
+        }
+    }
+
+    /// The cell of this exit that is the nearest from a point.
+    pub fn nearest_cell(&self, from: &Point) -> Point {
+        let mut nearest = self.cells[0];
+        for cell in &self.cells {
+            if cell.square_distance_to(from) < nearest.square_distance_to(from) {
+                nearest = *cell;
+            }
+        }
+        nearest
+    }
+
+    pub fn distance_from(&self, from: &Point) -> f32 {
+        self.nearest_cell(from).distance_to(from)
+    }
+
+    /// Estimated number of turns needed to leave by this exit from a point :
+    /// the walk to the exit and the wait behind the crowd gathered around it.
+    pub fn estimated_time(&self, from: &Point, terrain: &Terrain) -> f32 {
+        let radius = CROWD_RADIUS + self.width() as isize / 2;
+        let crowd = terrain.count_persons_around(&self.center(), radius);
+        self.distance_from(from) + crowd as f32 / self.width() as f32
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Exit : {{ id : {}, center : {}, width : {} }}", self.id, self.center(), self.width())
+    }
+}
+
+// ***********
+// EXIT CHOICE
+// ***********
+/// How a Person picks the exit it heads to.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ExitChoice {
+    /// the nearest exit, left for another one when it gets congested
+    #[default]
+    Nearest,
+    /// the exit with the shortest estimated time (walk and wait), reviewed regularly
+    LeastWait,
+    /// an exit given to the Person (by default, exits are given in turn by id), never left
+    Assigned,
+}
+
+impl ExitChoice {
+
+    /// First choice of a Person among the exits of a Terrain (there must be at least one).
+    pub fn choose(&self, person: &Person, terrain: &Terrain) -> usize {
+        let exits = terrain.get_exits();
+        match *self {
+            ExitChoice::Nearest => min_exit_by(exits, |e| e.distance_from(&person.position)),
+            ExitChoice::LeastWait => min_exit_by(exits, |e| e.estimated_time(&person.position, terrain)),
+            ExitChoice::Assigned => person.id % exits.len(),
+        }
+    }
+
+    /// true if the Person may leave its exit for a less congested one.
+    pub fn can_switch(&self) -> bool {
+        *self != ExitChoice::Assigned
+    }
+}
+
+/// id of the exit with the lowest value
+pub fn min_exit_by<F: Fn(&Exit) -> f32>(exits: &[Exit], value: F) -> usize {
+    let mut best = (0, f32::MAX);
+    for exit in exits {
+        let v = value(exit);
+        if v < best.1 {
+            best = (exit.id, v);
+        }
+    }
+    best.0
+}
+
+/// Parses the name of an exit choice : nearest, least-wait or assigned
+impl FromStr for ExitChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExitChoice, String> {
+        match s {
+            "nearest" => Ok(ExitChoice::Nearest),
+            "least-wait" => Ok(ExitChoice::LeastWait),
+            "assigned" => Ok(ExitChoice::Assigned),
+            _ => Err(format!("unknown exit choice \"{}\", expected nearest, least-wait or assigned", s)),
+        }
+    }
+}
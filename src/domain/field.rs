@@ -0,0 +1,119 @@
+use super::Point;
+use super::terrain::Terrain;
+use super::storage::ChunkedGrid;
+
+use std::cmp;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32;
+use std::mem;
+
+/// Turns from the targets beyond which a field is not computed, so that it stays small on a large Terrain.
+pub const FIELD_RADIUS: f32 = 1024.;
+
+// **************
+// DISTANCE FIELD
+// **************
+/// Number of turns needed to reach the nearest target from each cell of a Terrain,
+/// walking on non obstacle cells and spending on each cell as many turns as its cost.
+/// Persons are ignored : they move, obstacles and costs do not.
+/// Only the cells within `FIELD_RADIUS` turns of a target are computed, see `get` for the others.
+#[derive(Debug, Clone)]
+pub struct DistanceField {
+    layout_version: u64, // version of the Terrain layout the field was computed for
+    distances: ChunkedGrid<f32>,
+    targets: Vec<Point>,
+    bounded: bool, // some cells were farther than the radius
+}
+
+// cell waiting in the Dijkstra queue, the nearest first
+#[derive(PartialEq)]
+struct Visit(f32, Point);
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Visit) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Visit) -> Ordering {
+        // reversed : BinaryHeap is a max heap
+        other.0.partial_cmp(&self.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl DistanceField {
+
+    /// Computes the field of a Terrain towards some targets (Dijkstra from the targets).
+    pub fn compute(terrain: &Terrain, targets: &[Point]) -> DistanceField {
+        DistanceField::compute_for_footprint(terrain, targets, 1)
+    }
+
+    /// Same as `compute` for a Person occupying a square of cells :
+    /// distances are the ones of the lower left cell, the whole square must fit between the obstacles.
+    pub fn compute_for_footprint(terrain: &Terrain, targets: &[Point], size: usize) -> DistanceField {
+        let mut distances = ChunkedGrid::new(terrain.xsize, terrain.ysize, f32::INFINITY);
+        let mut queue: BinaryHeap<Visit> = BinaryHeap::new();
+        let mut bounded = false;
+
+        for target in targets {
+            if terrain.is_in_bounds(target) {
+                distances.set(target.x as usize, target.y as usize, 0.);
+                queue.push(Visit(0., *target));
+            }
+        }
+
+        while let Some(Visit(dist, pt)) = queue.pop() {
+            if dist > distances.get(pt.x as usize, pt.y as usize) {
+                continue; // already reached by a shorter path
+            }
+            for x in (pt.x - 1)..(pt.x + 2) {
+                for y in (pt.y - 1)..(pt.y + 2) {
+                    let next = Point{x, y};
+                    if !terrain.is_walkable_footprint(&next, size) {
+                        continue;
+                    }
+                    // from `next`, a Person reaches `pt` after leaving `next`
+                    let next_dist = dist + terrain.get_pt_cost(&next) as f32;
+                    if next_dist > FIELD_RADIUS {
+                        bounded = true;
+                        continue;
+                    }
+                    if next_dist < distances.get(x as usize, y as usize) {
+                        distances.set(x as usize, y as usize, next_dist);
+                        queue.push(Visit(next_dist, next));
+                    }
+                }
+            }
+        }
+
+        let targets = targets.iter().cloned().filter(|t| terrain.is_in_bounds(t)).collect();
+        DistanceField { layout_version: terrain.get_layout_version(), distances, targets, bounded }
+    }
+
+    /// Turns needed to reach the nearest target, infinite if no target can be reached.
+    /// Beyond the radius, the distance is estimated as the radius plus the moves to the nearest target
+    /// in a straight line : it decreases towards the computed cells, whether they can be reached or not.
+    pub fn get(&self, point: &Point) -> f32 {
+        let dist = self.distances.get(point.x as usize, point.y as usize);
+        if dist.is_finite() || !self.bounded {
+            return dist;
+        }
+        let moves = self.targets.iter()
+            .map(|t| cmp::max((t.x - point.x).abs(), (t.y - point.y).abs()))
+            .min();
+        moves.map_or(f32::INFINITY, |m| FIELD_RADIUS + m as f32)
+    }
+
+    /// Approximate memory used by the field, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.distances.memory_size() + self.targets.len() * mem::size_of::<Point>()
+    }
+
+    pub fn get_layout_version(&self) -> u64 {
+        self.layout_version
+    }
+}
@@ -0,0 +1,30 @@
+// ******
+// HAZARD
+// ******
+/// Rules driving the hazard layer of a Terrain (fire, smoke...).
+/// The intensity of a hazard cell ranges from 1 to 255, 0 meaning no hazard.
+#[derive(Debug, Copy, Clone)]
+pub struct HazardRules {
+    /// probability, at each tick, that a hazard cell spreads to each of its free neighbours
+    pub spread_probability: f32,
+    /// intensity gained by a hazard cell at each tick (and intensity of newly reached cells)
+    pub growth: u8,
+    /// from this intensity, a cell costs twice as many turns to leave
+    pub slow_level: u8,
+    /// from this intensity, nobody can move into the cell
+    pub block_level: u8,
+    /// from this intensity, a Person standing on the cell is a casualty
+    pub lethal_level: u8,
+}
+
+impl Default for HazardRules {
+    fn default() -> HazardRules {
+        HazardRules {
+            spread_probability: 0.05,
+            growth: 8,
+            slow_level: 1,
+            block_level: 128,
+            lethal_level: 192,
+        }
+    }
+}
@@ -1,5 +1,17 @@
 pub mod terrain;
 pub mod person;
+pub mod config;
+pub mod events;
+pub mod hazard;
+pub mod report;
+pub mod building;
+pub mod storage;
+pub mod field;
+pub mod strategy;
+pub mod exit;
+pub mod population;
+pub mod perception;
+pub mod arrivals;
 
 pub const XSIZE:  usize = 512;
 pub const YSIZE:  usize = 128;
@@ -7,6 +19,7 @@ pub const NBEXIT: usize = 4;
 
 use std::fmt; // formatting for console display
 use std::cmp;
+use std::str::FromStr;
 
 // *****
 // POINT
@@ -24,6 +37,21 @@ impl Point {
         // we only want ordering feature : we wont compute the square root :
         ((other.x - self.x).pow(2) + (other.y - self.y).pow(2)) as f32
     }
+
+    fn distance_to(&self, other: &Point) -> f32 {
+        self.square_distance_to(other).sqrt()
+    }
+
+    /// Cells of the square of the given side whose lower left corner is this point.
+    pub fn footprint(&self, size: usize) -> Vec<Point> {
+        let mut cells = Vec::with_capacity(size * size);
+        for x in self.x..self.x + size as isize {
+            for y in self.y..self.y + size as isize {
+                cells.push(Point{x, y});
+            }
+        }
+        cells
+    }
 }
 
 impl cmp::PartialEq for Point {
@@ -36,6 +64,21 @@ impl cmp::PartialEq for Point {
     }
 }
 
+/// Parses a point written as `x,y`
+impl FromStr for Point {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Point, String> {
+        let coords: Vec<&str> = s.split(',').collect();
+        if coords.len() != 2 {
+            return Err(format!("\"{}\" is not a point, expected x,y", s));
+        }
+        let x = coords[0].trim().parse::<isize>().map_err(|e| format!("invalid x in \"{}\" : {}", s, e))?;
+        let y = coords[1].trim().parse::<isize>().map_err(|e| format!("invalid y in \"{}\" : {}", s, e))?;
+        Ok(Point{x, y})
+    }
+}
+
 impl fmt::Display for Point {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "(x:{}, y:{})", self.x, self.y)
@@ -44,19 +87,117 @@ impl fmt::Display for Point {
 
 use domain::terrain::Terrain;
 use domain::person::Person;
+use domain::config::SimConfig;
+use domain::building::Building;
+use domain::population::class_of;
+use domain::perception::Knowledge;
+
+use std::collections::HashMap;
+
+/// Creates a sample Terrain and applies the layout options of the configuration to it.
+pub fn initialize_terrain(config: &SimConfig, xsize: usize, ysize: usize) -> Terrain {
+    let mut terrain: Terrain = Terrain::new_sample(xsize, ysize);
+    terrain.set_seed(config.seed);
+    if let Some(ref cost_map) = config.cost_map {
+        terrain.load_cost_map(cost_map)
+            .expect("Could not read the cost map file");
+    }
+    if let Some(ref timeline) = config.timeline {
+        terrain.load_timeline(timeline)
+            .expect("Could not read the timeline file");
+    }
+    for exit in &config.exits {
+        terrain.add_exit_point(*exit);
+    }
+    for sign in &config.signs {
+        terrain.add_sign(*sign);
+    }
+    terrain.set_hazard_rules(config.hazard_rules);
+    for hazard in &config.hazards {
+        terrain.ignite(hazard, config.hazard_rules.growth);
+    }
+    terrain
+}
+
+// behaviour options of the Persons
+fn configure_person(config: &SimConfig, pers: &mut Person) {
+    pers.hazard_aversion = config.hazard_aversion;
+    pers.set_strategy(config.strategy.build());
+    pers.exit_choice = config.exit_choice;
+    pers.exit_switch_margin = config.exit_switch_margin;
+    pers.group_distance = config.group_distance;
+    pers.memory_size = config.memory;
+    pers.swap = config.swap;
+    if config.perception > 0. {
+        pers.knowledge = Some(Knowledge::new(config.perception));
+    }
+}
+
+// Persons are gathered in groups of consecutive ranks
+fn group_of(config: &SimConfig, rank: usize) -> Option<usize> {
+    if config.group_size > 1 { Some(rank / config.group_size) } else { None }
+}
+
+// largest footprint among the group of a Person (its own one without groups)
+fn group_footprint(config: &SimConfig, nb_pers: usize, rank: usize) -> u8 {
+    let group = rank / config.group_size;
+    (group * config.group_size..cmp::min((group + 1) * config.group_size, nb_pers))
+        .map(|member| config.population[class_of(member, &config.population)].footprint)
+        .max().unwrap_or(1)
+}
+
+// the first member of a group is placed at random, where its largest member can be placed too,
+// the other ones next to it
+fn find_place(config: &SimConfig, terrain: &Terrain, nb_pers: usize, rank: usize, size: usize, leader: &mut Option<Point>) -> Point {
+    let follower = config.group_size > 1 && !rank.is_multiple_of(config.group_size);
+    let pt = match *leader {
+        Some(ref lead) if follower => terrain.get_free_area_near(lead, size),
+        _ => terrain.get_random_free_area(cmp::max(size, group_footprint(config, nb_pers, rank) as usize)),
+    }.expect("Not enough free positions on the Terrain for all Persons");
+    if !follower {
+        *leader = Some(pt);
+    }
+    pt
+}
+
+// stairs are too narrow for wheelchairs and carts : they start on the ground floor with their whole group,
+// the other groups are spread evenly on the floors
+fn floor_of(config: &SimConfig, nb_pers: usize, rank: usize) -> usize {
+    if group_footprint(config, nb_pers, rank) > 1 { 0 } else { rank / config.group_size % config.floors }
+}
 
-pub fn initialize_terrain_and_users(nb_pers: usize, xsize: usize, ysize: usize) -> (Terrain, Vec<Person>) {
+/// A group walks at the speed of its slowest member.
+pub fn slow_down_groups(persons: &mut [Person]) {
+    let mut speeds: HashMap<usize, f32> = HashMap::new();
+    for pers in persons.iter() {
+        if let Some(group) = pers.group {
+            let speed = speeds.entry(group).or_insert(pers.speed);
+            *speed = speed.min(pers.speed);
+        }
+    }
+    for pers in persons.iter_mut() {
+        if let Some(group) = pers.group {
+            pers.speed = speeds[&group];
+        }
+    }
+}
+
+pub fn initialize_terrain_and_users(config: &SimConfig, nb_pers: usize, xsize: usize, ysize: usize) -> (Terrain, Vec<Person>) {
     // ********* INITIALIZATION ********
 
     // Initialize the terrain and place persons in it :
-    let mut terrain: Terrain = Terrain::new_sample(xsize, ysize);
+    let mut terrain: Terrain = initialize_terrain(config, xsize, ysize);
     #[derive(Debug)]
     let mut persons: Vec<Person> = Vec::with_capacity(nb_pers as usize);
+    let mut leader: Option<Point> = None;
 
     for i in 1..nb_pers + 1 {
-        let pt: Point = terrain.get_random_free_point()
-            .expect("Not enough free positions on the Terrain for all Persons");
+        let class = class_of(i - 1, &config.population);
+        let pt: Point = find_place(config, &terrain, nb_pers, i - 1, config.population[class].footprint as usize, &mut leader);
         let mut new_pers = Person::new(i*10, pt);
+        config.population[class].apply(class, &mut new_pers);
+        configure_person(config, &mut new_pers);
+        new_pers.group = group_of(config, i - 1);
         new_pers.place_on_terrain(&mut terrain);
         //terrain.set_pt(&new_pers.position, new_pers.id as isize); // occupy }
         debug!("placing : {}", &new_pers);
@@ -64,8 +205,41 @@ pub fn initialize_terrain_and_users(nb_pers: usize, xsize: usize, ysize: usize)
     }
     info!(" pers in terrain : {}", terrain.count_persons_in_terrain());
     debug!(" expected pers in terrain : {}", nb_pers);
-    assert_eq!(terrain.count_persons_in_terrain(), nb_pers);
+    assert_eq!(terrain.count_persons_in_terrain(), persons.iter().map(|p| (p.footprint as usize).pow(2)).sum());
+    slow_down_groups(&mut persons);
     debug!("persons array : {:?}", persons);
 
     (terrain, persons)
-}
\ No newline at end of file
+}
+
+/// Creates a Building of `config.floors` stacked sample floors
+/// and spreads the groups of Persons evenly on its floors (the ones with a member larger than a cell on the ground floor).
+pub fn initialize_building_and_users(config: &SimConfig, nb_pers: usize, xsize: usize, ysize: usize) -> (Building, Vec<Person>) {
+    let floors: Vec<Terrain> = (0..config.floors)
+        .map(|_| initialize_terrain(config, xsize, ysize))
+        .collect();
+    let building = Building::new_stacked(floors, config.stair_delay, config.stair_capacity);
+
+    let mut persons: Vec<Person> = Vec::with_capacity(nb_pers as usize);
+    let mut leader: Option<Point> = None;
+    for i in 1..nb_pers + 1 {
+        let class = class_of(i - 1, &config.population);
+        let floor = floor_of(config, nb_pers, i - 1);
+        let pterrain = building.get_floor(floor);
+        let mut terrain = pterrain.lock().unwrap();
+
+        let pt: Point = find_place(config, &terrain, nb_pers, i - 1, config.population[class].footprint as usize, &mut leader);
+        let mut new_pers = Person::new(i*10, pt);
+        new_pers.floor = floor;
+        config.population[class].apply(class, &mut new_pers);
+        configure_person(config, &mut new_pers);
+        new_pers.group = group_of(config, i - 1);
+        new_pers.place_on_terrain(&mut terrain);
+        debug!("placing on floor {} : {}", floor, &new_pers);
+        persons.push(new_pers);
+    }
+    slow_down_groups(&mut persons);
+    info!(" pers in building : {}", persons.len());
+
+    (building, persons)
+}
@@ -0,0 +1,210 @@
+extern crate rand;
+
+use super::Point;
+use super::terrain::Terrain;
+
+use std::collections::{HashMap, HashSet};
+use std::cmp;
+use std::fmt; // formatting for console display
+use std::str::FromStr;
+use self::rand::Rng;
+
+/// A wandering Person picks another point to explore once this close to the current one...
+const WANDER_REACHED: f32 = 2.;
+/// ... or after this number of looks (the point may be behind an obstacle).
+/// Persons also alternate between following the others and exploring on their own
+/// for this number of looks (a crowd following itself may go nowhere).
+const WANDER_LOOKS: u32 = 40;
+/// Random points tried to find a part of the floor not explored yet,
+/// within this number of perception radiuses of the Person.
+const WANDER_TRIES: usize = 8;
+const WANDER_RANGE: f32 = 4.;
+
+// ****
+// SIGN
+// ****
+/// A signage cell : Persons seeing it learn the way to a point (an exit, a corridor...).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sign {
+    pub at: Point,
+    pub towards: Point,
+}
+
+/// Parses a sign written as `x,y:x,y` : the cell of the sign, then the point it shows
+impl FromStr for Sign {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Sign, String> {
+        let points: Vec<&str> = s.split(':').collect();
+        if points.len() != 2 {
+            return Err(format!("\"{}\" is not a sign, expected x,y:x,y", s));
+        }
+        Ok(Sign { at: points[0].parse::<Point>()?, towards: points[1].parse::<Point>()? })
+    }
+}
+
+impl fmt::Display for Sign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sign : {{ at : {}, towards : {} }}", self.at, self.towards)
+    }
+}
+
+// *********
+// KNOWLEDGE
+// *********
+/// What a Person with a limited perception knows of the floor it is on :
+/// it only sees within its perception radius, and learns as it moves.
+#[derive(Debug, Clone)]
+pub struct Knowledge {
+    /// distance up to which the Person sees obstacles, other Persons, exits and signs
+    pub radius: f32,
+    exits: Vec<Point>, // exit points seen
+    sign: Option<Point>, // the way shown by the last sign seen, until the Person gets there
+    flow: Option<Point>, // the way the Persons in sight walk to
+    seen: HashMap<isize, Point>, // Persons in sight at the last look, by id
+    wander: Option<Point>, // the point the Person explores to
+    wander_looks: u32,
+    looks: u32,
+    explored: HashSet<(isize, isize)>, // squares of the floor (of the side of the radius) already seen
+}
+
+impl Knowledge {
+
+    pub fn new(radius: f32) -> Knowledge {
+        Knowledge { radius, exits: Vec::new(), sign: None, flow: None, seen: HashMap::new(), wander: None, wander_looks: 0, looks: 0,
+            explored: HashSet::new() }
+    }
+
+    pub fn knows_exit(&self) -> bool {
+        !self.exits.is_empty()
+    }
+
+    pub fn get_known_exits(&self) -> &[Point] {
+        &self.exits
+    }
+
+    /// Looks around from a position : remembers the exits in sight, follows the signs,
+    /// and without them, the other Persons or a point to explore (changed when `blocked`).
+    pub fn look<R: Rng>(&mut self, position: &Point, terrain: &Terrain, blocked: bool, rng: &mut R) {
+        let square = self.square_of(position);
+        self.explored.insert(square);
+        self.looks = self.looks.wrapping_add(1);
+        for exit in terrain.get_exit_points() {
+            if !self.exits.contains(exit) && self.sees(position, exit, terrain) {
+                debug!("{} sees exit {}", position, exit);
+                self.exits.push(*exit);
+            }
+        }
+        if self.knows_exit() {
+            return;
+        }
+
+        if self.sign.is_some_and(|to| to.distance_to(position) < WANDER_REACHED) {
+            self.sign = None;
+        }
+        for sign in terrain.get_signs() {
+            if self.sees(position, &sign.at, terrain) && sign.towards.distance_to(position) >= WANDER_REACHED {
+                self.sign = Some(sign.towards);
+            }
+        }
+        if self.sign.is_some() {
+            return;
+        }
+
+        let follows = (self.looks / WANDER_LOOKS).is_multiple_of(2);
+        self.flow = if follows { self.look_at_persons(position, terrain) } else { None };
+        if self.flow.is_some() {
+            return;
+        }
+
+        let reached = self.wander.is_none_or(|to| to.distance_to(position) < WANDER_REACHED);
+        self.wander_looks += 1;
+        if reached || blocked || self.wander_looks > WANDER_LOOKS {
+            self.wander_looks = 0;
+            self.wander = self.pick_unexplored(position, terrain, rng);
+        }
+    }
+
+    fn square_of(&self, point: &Point) -> (isize, isize) {
+        let side = cmp::max(self.radius as isize, 1);
+        (point.x / side, point.y / side)
+    }
+
+    // a random point around the Person, preferably in a square not explored yet
+    fn pick_unexplored<R: Rng>(&self, position: &Point, terrain: &Terrain, rng: &mut R) -> Option<Point> {
+        let range = cmp::max((self.radius * WANDER_RANGE) as isize, 2);
+        let (xmax, ymax) = (terrain.xsize as isize - 1, terrain.ysize as isize - 1);
+        let mut point = None;
+        for _ in 0..WANDER_TRIES {
+            let candidate = Point {
+                x: cmp::min(cmp::max(position.x + rng.gen_range(-range, range + 1), 0), xmax),
+                y: cmp::min(cmp::max(position.y + rng.gen_range(-range, range + 1), 0), ymax),
+            };
+            point = Some(candidate);
+            if !self.explored.contains(&self.square_of(&candidate)) {
+                break;
+            }
+        }
+        point
+    }
+
+    // the mean way walked since the last look by the Persons in sight
+    fn look_at_persons(&mut self, position: &Point, terrain: &Terrain) -> Option<Point> {
+        let range = self.radius as isize;
+        let own = terrain.get_pt_val(position);
+        let mut in_sight: HashMap<isize, Point> = HashMap::new();
+        let (mut dx, mut dy, mut moving) = (0, 0, 0);
+        for x in position.x - range..position.x + range + 1 {
+            for y in position.y - range..position.y + range + 1 {
+                let cell = Point { x, y };
+                if !terrain.is_in_bounds(&cell) {
+                    continue;
+                }
+                let id = terrain.get_pt_val(&cell);
+                if id <= 0 || id == own || in_sight.contains_key(&id) || !self.sees(position, &cell, terrain) {
+                    continue;
+                }
+                if let Some(before) = self.seen.get(&id) {
+                    if *before != cell {
+                        dx += cell.x - before.x;
+                        dy += cell.y - before.y;
+                        moving += 1;
+                    }
+                }
+                in_sight.insert(id, cell);
+            }
+        }
+        self.seen = in_sight;
+        if moving == 0 || (dx == 0 && dy == 0) {
+            return None;
+        }
+        // far enough in that direction to keep the same heading for a while
+        let norm = ((dx * dx + dy * dy) as f32).sqrt();
+        Some(Point {
+            x: position.x + (dx as f32 / norm * self.radius).round() as isize,
+            y: position.y + (dy as f32 / norm * self.radius).round() as isize,
+        })
+    }
+
+    // within the radius, and no obstacle in between
+    fn sees(&self, from: &Point, to: &Point, terrain: &Terrain) -> bool {
+        from.distance_to(to) <= self.radius && terrain.is_visible(from, to)
+    }
+
+    /// The point the Person heads to : the nearest exit it knows, the way shown by a sign,
+    /// the way the others walk or a point to explore, in this order.
+    pub fn heading(&self, position: &Point) -> Option<Point> {
+        let mut nearest: Option<Point> = None;
+        for exit in &self.exits {
+            if nearest.is_none_or(|n| exit.square_distance_to(position) < n.square_distance_to(position)) {
+                nearest = Some(*exit);
+            }
+        }
+        nearest.or(self.sign).or(self.flow).or(self.wander)
+    }
+
+    /// Forgets what was learnt of the floor (the Person went down a stair).
+    pub fn forget(&mut self) {
+        *self = Knowledge::new(self.radius);
+    }
+}
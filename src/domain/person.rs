@@ -1,10 +1,55 @@
+extern crate rand;
+
 use super::Point;
 use super::terrain::Terrain;
+use super::strategy::{MovementStrategy, Decision, GreedyToAzimuth};
+use super::exit::{Exit, ExitChoice, EXIT_REVIEW_TICKS, min_exit_by};
+use super::perception::Knowledge;
 
 use std::fmt; // formatting for console display
 use std::cmp;
-use std::cmp::Ordering::Equal;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use self::rand::{Rng, SeedableRng};
+
+/// Turns a Person waits for its group before going on anyway
+/// (it may be standing in the way of the members it waits for).
+pub const MAX_GROUP_WAIT: u8 = 10;
+
+/// Number of recently left cells a Person remembers by default : none, the moves are the historical ones
+/// (see `GreedyToAzimuth`).
+pub const DEFAULT_MEMORY: usize = 0;
+
 
+// *********
+// LIFECYCLE
+// *********
+/// States of a Person during a simulation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Lifecycle {
+    /// not on a Terrain yet
+    Unplaced,
+    /// on a Terrain, not moving yet or waiting (for its group...)
+    Waiting,
+    /// moved during its last turn
+    Moving,
+    /// could not move during its last turn : no free cell, move refused, way not clear
+    Blocked,
+    /// left its floor by a stair of the Building, not on a Terrain until it reaches the floor below
+    InStair,
+    /// left the Building by an exit
+    Escaped,
+    /// engulfed by a hazard
+    Casualty,
+}
+
+impl fmt::Display for Lifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
 
 // ******
 // PERSON
@@ -13,70 +58,360 @@ use std::cmp::Ordering::Equal;
 pub struct Person {
     pub id: usize,
     pub position : Point,
-    pub has_escaped : bool,
+    pub floor : usize, // floor of the Building the Person is on
+    pub stair : Option<usize>, // stair of the Building the Person is walking down
+    state : Lifecycle,
+    state_tick : u64, // tick and instant of the last change of state
+    state_instant : Instant,
+    pub placed_tick : Option<u64>, // when the Person was placed on its first Terrain
+    pub placed_instant : Option<Instant>,
+    pub left_tick : Option<u64>, // tick of the Building when the Person escaped or became a casualty
+    pub left_instant : Option<Instant>,
+    pub blocked_ticks : u64, // ticks spent in the Blocked state
+    pub moves_cnt : u64,
+    pub distance : f32, // cells walked, diagonal moves count for their length
+    pub wait_turns : u8, // turns spent on the current cell
+    pub exposure_turns : u64, // turns spent on hazard cells
+    pub hazard_aversion : f32, // 0 : hazards are ignored when choosing a move
+    pub strategy : Arc<dyn MovementStrategy>,
+    pub rng : RefCell<rand::StdRng>, // random generator of the Person, seeded with its id
+    pub exit_choice : ExitChoice,
+    pub exit_switch_margin : f32, // turns to save before leaving an exit for another one
+    pub target_exit : Cell<Option<usize>>, // id of the exit of the current floor
+    exit_review_tick : Cell<u64>,
+    pub class : usize, // agent class in the population mix
+    pub speed : f32, // cells per turn, see AgentClass
+    pub patience : u8, // turns waited before moving without getting closer
+    pub panic : f32, // from 0 (own goal) to 1 (follows the crowd), see `Herding`
+    pub footprint : u8, // side of the square of cells occupied, position is its lower left cell
+    blocked_turns : u8,
+    pub group : Option<usize>, // group (family, friends...) the Person stays with
+    pub group_distance : f32, // maximum distance to the other members of the group
+    group_wait_turns : u8,
+    pub memory_size : usize, // number of recently left cells the Person avoids going back to
+    memory : VecDeque<Point>,
+    pub swap : bool, // exchanges cells with a face to face Person that wants its cell
+    pub knowledge : Option<Knowledge>, // what the Person saw, None if it knows the whole floor
 }
 
 impl Person {
 
     pub fn new_placed(terrain : &mut Terrain, id: usize, position : Point) -> Person {
-        terrain.set_pt_val(&position, id as isize);
-        Person {id, position, has_escaped :false}
+        terrain.place_person(&position, id);
+        let mut pers = Person {id, position, ..Person::new_unplaced(id)};
+        pers.placed(terrain.get_clock());
+        pers
 
     }
 
     pub fn new_unplaced(id: usize) -> Person {
-        Person { id, position: Point { x: 0, y: 0 }, has_escaped : true }
+        Person { id, position: Point { x: 0, y: 0 }, floor: 0, stair: None,
+            state: Lifecycle::Unplaced, state_tick: 0, state_instant: Instant::now(),
+            placed_tick: None, placed_instant: None, left_tick: None, left_instant: None,
+            blocked_ticks: 0, moves_cnt: 0, distance: 0.,
+            wait_turns: 0, exposure_turns: 0, hazard_aversion: 0.,
+            strategy: Arc::new(GreedyToAzimuth), rng: RefCell::new(rand::StdRng::from_seed(&[id])),
+            exit_choice: ExitChoice::default(), exit_switch_margin: 10.,
+            target_exit: Cell::new(None), exit_review_tick: Cell::new(0),
+            class: 0, speed: 1., patience: 0, panic: 0., footprint: 1, blocked_turns: 0,
+            group: None, group_distance: 3., group_wait_turns: 0,
+            memory_size: DEFAULT_MEMORY, memory: VecDeque::with_capacity(DEFAULT_MEMORY), swap: false,
+            knowledge: None }
     }
 
     pub fn new(id : usize, position : Point) -> Person {
-        Person { id, position, has_escaped : false }
+        Person { id, position, ..Person::new_unplaced(id) }
+    }
+
+    pub fn get_state(&self) -> Lifecycle {
+        self.state
+    }
+
+    /// Moves the Person to a new state at the given tick (of the clock shared by the floors, see `Terrain::get_clock`) :
+    /// the time spent blocked is accumulated, leaving times are recorded.
+    pub fn set_state(&mut self, state: Lifecycle, tick: u64) {
+        if state == self.state {
+            return;
+        }
+        trace!("tick {} : I, {} go from {} to {}", tick, self.id, self.state, state);
+        if self.state == Lifecycle::Blocked {
+            self.blocked_ticks += tick.saturating_sub(self.state_tick);
+        }
+        let now = Instant::now();
+        match state {
+            Lifecycle::Escaped | Lifecycle::Casualty => {
+                self.left_tick = Some(tick);
+                self.left_instant = Some(now);
+            }
+            _ => {
+                // back in the Building : it was only a stair
+                self.left_tick = None;
+                self.left_instant = None;
+            }
+        }
+        self.state = state;
+        self.state_tick = tick;
+        self.state_instant = now;
+    }
+
+    // first placement : the clock of the Person starts
+    fn placed(&mut self, tick: u64) {
+        if self.placed_tick.is_none() {
+            self.placed_tick = Some(tick);
+            self.placed_instant = Some(Instant::now());
+        }
+        self.set_state(Lifecycle::Waiting, tick);
+    }
+
+    /// Ticks between the placement of the Person and its escape.
+    pub fn time_to_escape(&self) -> Option<u64> {
+        match (self.state, self.placed_tick, self.left_tick) {
+            (Lifecycle::Escaped, Some(placed), Some(left)) => Some(left.saturating_sub(placed)),
+            _ => None,
+        }
+    }
+
+    /// Same as `time_to_escape`, in wall clock time.
+    pub fn duration_to_escape(&self) -> Option<Duration> {
+        match (self.state, self.placed_instant, self.left_instant) {
+            (Lifecycle::Escaped, Some(placed), Some(left)) => Some(left.duration_since(placed)),
+            _ => None,
+        }
     }
 
 
-    // Select the best available move that reduces most the distance to the azimuth point
+    // Select the best available move according to the strategy of the Person
     // or stay where you are.
-    pub fn choose_best_move (&self, possible_moves: &Vec<Point>) -> Point {
-        let azimuth: Point = Point{x: -2, y: 130};
+    pub fn choose_best_move (&self, terrain: &Terrain, possible_moves: &Vec<Point>) -> Point {
+        match self.strategy.decide(self, possible_moves, terrain) {
+            Decision::MoveTo(point) => point,
+            Decision::Stay => Point{x: self.position.x, y: self.position.y},
+        }
+    }
 
-        #[derive(Debug)] // to allow println for debugging purposes.
-        let mut moves_and_dist: Vec<(&Point, f32)> = possible_moves.iter()
-            .map(|x| (x, x.square_distance_to( &azimuth)))
-            .collect();
+    /// Changes the way the Person chooses its moves.
+    pub fn set_strategy(&mut self, strategy: Arc<dyn MovementStrategy>) {
+        self.strategy = strategy;
+    }
 
-        moves_and_dist.sort_by(
-            |x, y| { x.1.partial_cmp((&y.1))
-                .unwrap_or(Equal)
-            }
-        );
-        debug!("debug sort :{:?}",moves_and_dist); // debug
-        match moves_and_dist.first() {
-            Some(&(ref point, _)) => Point{x:point.x, y:point.y},
-            None => Point{x: self.position.x, y: self.position.y}, // todo : stay where you are for now...
+    /// The exit the Person heads to, chosen the first time it is needed
+    /// and reviewed regularly : if another exit is less congested, the Person may switch to it.
+    /// None if the Terrain has no exit.
+    pub fn target_exit<'a>(&self, terrain: &'a Terrain) -> Option<&'a Exit> {
+        let exits = terrain.get_exits();
+        if exits.is_empty() {
+            return None;
         }
+
+        let tick = terrain.get_tick();
+        let target = match self.target_exit.get() {
+            Some(current) if current < exits.len() => {
+                if self.exit_choice.can_switch() && tick >= self.exit_review_tick.get() {
+                    self.exit_review_tick.set(tick + EXIT_REVIEW_TICKS);
+                    let best = min_exit_by(exits, |e| e.estimated_time(&self.position, terrain));
+                    let saved = exits[current].estimated_time(&self.position, terrain)
+                        - exits[best].estimated_time(&self.position, terrain);
+                    if saved > self.exit_switch_margin {
+                        info!("tick {} : Person {} switches from exit {} to exit {} ({:.1} turns saved)",
+                              tick, self.id, current, best, saved);
+                        best
+                    } else {
+                        current
+                    }
+                } else {
+                    current
+                }
+            }
+            _ => {
+                // no exit yet, or the exits of the Terrain changed
+                let chosen = self.exit_choice.choose(self, terrain);
+                self.exit_review_tick.set(tick + EXIT_REVIEW_TICKS);
+                debug!("tick {} : Person {} heads to {} ({:?})", tick, self.id, exits[chosen], self.exit_choice);
+                chosen
+            }
+        };
+        self.target_exit.set(Some(target));
+        exits.get(target)
     }
 
     pub fn place_on_terrain(&mut self, terrain: &mut Terrain){
-        terrain.set_pt_val(&self.position, self.id as isize);
-        self.has_escaped = false;
+        terrain.place_footprint(&self.position, self.footprint as usize, self.id);
+        if let Some(group) = self.group {
+            terrain.join_group(group, self.id, &self.position);
+        }
+        self.placed(terrain.get_clock());
     }
 
     pub fn remove_from_terrain(&mut self, terrain: &mut Terrain){
-        terrain.set_pt_val(&self.position, 0 as isize);
-        self.has_escaped = true;
+        terrain.remove_footprint(&self.position, self.footprint as usize);
+        terrain.forget_intent(self.id);
+        if let Some(group) = self.group {
+            terrain.leave_group(group, self.id);
+        }
+    }
+
+    // the occupied neighbour cell the Person would rather be on than its own cell, the best one
+    fn wanted_cell(&self, terrain: &Terrain) -> Option<Point> {
+        let here = self.strategy.score(self, &self.position, terrain);
+        let mut best: Option<(Point, f32)> = None;
+        let corner = Point{x: self.position.x - 1, y: self.position.y - 1};
+        for cell in corner.footprint(3) {
+            let occupant = if terrain.is_in_bounds(&cell) { terrain.get_pt_val(&cell) } else { -1 };
+            if occupant <= 0 || occupant == self.id as isize {
+                continue;
+            }
+            let score = self.strategy.score(self, &cell, terrain);
+            if score < here && best.is_none_or(|(_, s)| score < s) {
+                best = Some((cell, score));
+            }
+        }
+        best.map(|(cell, _)| cell)
+    }
+
+    // instead of waiting, face to face Persons exchange their cells : true if the Person moved
+    fn try_swap(&mut self, terrain: &mut Terrain) -> bool {
+        if !self.swap || self.footprint != 1 {
+            return false;
+        }
+        match self.wanted_cell(terrain) {
+            Some(wanted) => {
+                let here = self.position;
+                if terrain.swap_or_wait(self.id, &here, &wanted) {
+                    self.blocked_turns = 0;
+                    self.swapped_to(terrain, wanted);
+                    return true;
+                }
+                false
+            }
+            None => false,
+        }
+    }
+
+    // the Person has been moved to another cell by a swap
+    fn swapped_to(&mut self, terrain: &mut Terrain, new_point: Point) {
+        trace!("I, {} swapped to : {}", self.id, new_point);
+        let left = self.position;
+        self.remember(left);
+        self.distance += left.distance_to(&new_point);
+        self.position = new_point;
+        self.wait_turns = 0;
+        self.moves_cnt += 1;
+        self.set_state(Lifecycle::Moving, terrain.get_clock());
+        if let Some(group) = self.group {
+            terrain.move_in_group(group, self.id, &new_point);
+        }
+    }
+
+    /// true if the Person can move to the point without leaving its group behind :
+    /// it does not get farther than the group distance from the members behind it
+    /// (the members farther from the exits), and it leaves by an exit only once they all caught up.
+    fn keeps_up_with_group(&self, terrain: &Terrain, group: usize, dst: &Point) -> bool {
+        let others: Vec<Point> = terrain.get_group_members(group).iter()
+            .filter(|&&(id, _)| id != self.id)
+            .map(|&(_, pos)| pos)
+            .collect();
+        if terrain.is_exit_footprint(dst, self.footprint as usize) {
+            return others.iter().all(|pos| pos.distance_to(&self.position) <= self.group_distance);
+        }
+        let here = terrain.get_exit_distance(&self.position);
+        others.iter().all(|pos| {
+            pos.distance_to(dst) <= self.group_distance ||
+                pos.distance_to(dst) < pos.distance_to(&self.position) ||
+                terrain.get_exit_distance(pos) < here // ahead of us : it waits for us
+        })
+    }
+
+    /// true if the Person recently left this cell.
+    pub fn remembers(&self, point: &Point) -> bool {
+        self.memory.contains(point)
+    }
+
+    // remembers the cell just left, forgets the oldest one
+    fn remember(&mut self, point: Point) {
+        if self.memory_size == 0 {
+            return;
+        }
+        if self.memory.len() >= self.memory_size {
+            self.memory.pop_front();
+        }
+        self.memory.push_back(point);
+    }
+
+    /// Forgets the recently left cells and what was seen (they were on another floor).
+    pub fn forget(&mut self) {
+        self.memory.clear();
+        if let Some(ref mut knowledge) = self.knowledge {
+            knowledge.forget();
+        }
+    }
+
+    /// The point a Person with a limited perception heads to (see `Knowledge::heading`),
+    /// None if it knows the whole floor.
+    pub fn heading(&self) -> Option<Point> {
+        self.knowledge.as_ref().and_then(|k| k.heading(&self.position))
+    }
+
+    // a Person with a limited perception learns from what it sees
+    fn look_around(&mut self, terrain: &Terrain) {
+        let blocked = self.state == Lifecycle::Blocked;
+        if let Some(ref mut knowledge) = self.knowledge {
+            knowledge.look(&self.position, terrain, blocked, &mut *self.rng.borrow_mut());
+        }
+    }
+
+    /// true if the point is one of the cells occupied by the Person.
+    pub fn covers(&self, point: &Point) -> bool {
+        let size = self.footprint as isize;
+        point.x >= self.position.x && point.x < self.position.x + size &&
+            point.y >= self.position.y && point.y < self.position.y + size
+    }
+
+
+    /// true once the Person is out of the Terrain : escaped or casualty
+    pub fn has_left(&self) -> bool {
+        self.has_escaped() || self.is_casualty()
+    }
+
+    pub fn has_escaped(&self) -> bool {
+        self.state == Lifecycle::Escaped
+    }
+
+    pub fn is_casualty(&self) -> bool {
+        self.state == Lifecycle::Casualty
     }
 
 
     pub fn move_to(&mut self, terrain: &mut Terrain, new_point: &Point) {
-        if self.has_escaped == true {
-        } else if terrain.get_exit_points().contains(new_point) {
-            terrain.move_src_to_dst(&self.position, new_point); // should just increase exit counts
-            trace!("I escaped : {}", self.id);
-            self.has_escaped = true;
+        if self.has_left() {
+        } else if terrain.move_footprint(&self.position, new_point, self.footprint as usize).is_none() {
+            // the cell has been taken or blocked (closing door...) since we looked at it
+            trace!("I, {} could not move to : {}", self.id, new_point);
+            self.set_state(Lifecycle::Blocked, terrain.get_clock());
+        } else if terrain.is_exit_footprint(new_point, self.footprint as usize) {
+            self.distance += self.position.distance_to(new_point);
+            self.position = *new_point; // remember the exit we took
+            self.moves_cnt += 1;
+            if terrain.has_stair_exits() {
+                trace!("I, {} take a stair : {}", self.id, new_point);
+                self.set_state(Lifecycle::InStair, terrain.get_clock());
+            } else {
+                trace!("I escaped : {}", self.id);
+                self.set_state(Lifecycle::Escaped, terrain.get_clock());
+            }
             self.remove_from_terrain(terrain);
         } else {
-        terrain.move_src_to_dst(&self.position, new_point);
-        self.position.x = new_point.x; // change internal position (copy of x and y)
-        self.position.y = new_point.y;
+            let left = self.position;
+            self.remember(left);
+            self.distance += left.distance_to(new_point);
+            self.position.x = new_point.x; // change internal position (copy of x and y)
+            self.position.y = new_point.y;
+            self.wait_turns = 0;
+            self.moves_cnt += 1;
+            self.set_state(Lifecycle::Moving, terrain.get_clock());
+            terrain.forget_intent(self.id);
+            if let Some(group) = self.group {
+                terrain.move_in_group(group, self.id, new_point);
+            }
         }
     }
 
@@ -85,15 +420,101 @@ impl Person {
     /// from looking around to actually moving to another place
     /// (and mutating the Person and the Terrain).
     pub fn look_and_move(&mut self, terrain : &mut Terrain) {
+        self.take_turn(terrain);
+        terrain.end_turn();
+    }
+
+    fn take_turn(&mut self, terrain : &mut Terrain) {
         //println!("Dealing with : {}", self);
+        if self.has_left() {
+            return;
+        }
+        // the other Person of a swap moved us during its turn
+        let swapped = match terrain.take_swap(self.id) {
+            Some(new_point) => { self.swapped_to(terrain, new_point); true }
+            None => false,
+        };
+
+        // hazards : count the exposure, engulfed Persons do not move anymore
+        if terrain.get_hazard(&self.position) > 0 {
+            self.exposure_turns += 1;
+            terrain.record_exposure();
+        }
+        if terrain.is_lethal(&self.position) {
+            trace!("I, {} am a casualty here : {}", self.id, self.position);
+            self.set_state(Lifecycle::Casualty, terrain.get_clock());
+            terrain.remove_footprint(&self.position, self.footprint as usize);
+            terrain.forget_intent(self.id);
+            if let Some(group) = self.group {
+                terrain.leave_group(group, self.id);
+            }
+            terrain.remove_casualty(&self.position);
+            return;
+        }
+        if swapped {
+            return; // this turn is spent
+        }
+
+        // slow cells : stay until as many turns as the cell cost have been spent on it
+        self.wait_turns = self.wait_turns.saturating_add(1);
+        if self.wait_turns < terrain.get_traversal_cost(&self.position) {
+            trace!("I, {} am slowed down here : {}", self.id, self.position);
+            return;
+        }
+
+        // fast Persons may move several times, slow ones only now and then
+        for _ in 0..self.moves_this_turn() {
+            self.step(terrain);
+            if self.has_left() || self.wait_turns == 0 && terrain.get_traversal_cost(&self.position) > 1 {
+                break; // gone, or stopped by a slow cell
+            }
+        }
+    }
 
+    fn moves_this_turn(&self) -> u32 {
+        let whole = self.speed.trunc();
+        let extra = self.speed - whole;
+        if extra > 0. && self.rng.borrow_mut().next_f32() < extra {
+            whole as u32 + 1
+        } else {
+            whole as u32
+        }
+    }
+
+    // one move : look around, choose and move
+    fn step(&mut self, terrain : &mut Terrain) {
         // look around
-        let moves = terrain.list_possible_moves(&self.position);
+        self.look_around(terrain);
+        let moves = terrain.list_possible_footprint_moves(&self.position, self.footprint as usize);
 
         // select the best point (hope that no-one took it while thinking)
         //println!("Possible moves : {:?}", moves);
         #[derive(Debug)]
-        let good_point = self.choose_best_move(&moves);
+        let good_point = self.choose_best_move(terrain, &moves);
+
+        // groups stay together
+        if let Some(group) = self.group {
+            if good_point == self.position || self.keeps_up_with_group(terrain, group, &good_point) {
+                self.group_wait_turns = 0;
+            } else if self.group_wait_turns < MAX_GROUP_WAIT {
+                self.group_wait_turns += 1;
+                trace!("I, {} am waiting for my group : {}", self.id, self.position);
+                self.set_state(Lifecycle::Waiting, terrain.get_clock());
+                return;
+            } // else : waited long enough, go on until the group catches up
+        }
+
+        // patient Persons wait for their way to clear rather than stepping aside
+        if good_point != self.position && self.patience > 0 {
+            let closer = self.strategy.score(self, &good_point, terrain) < self.strategy.score(self, &self.position, terrain);
+            if !closer && self.blocked_turns < self.patience {
+                self.blocked_turns += 1;
+                trace!("I, {} am waiting for my way to clear : {}", self.id, self.position);
+                self.wait_for_way(terrain);
+                return;
+            }
+            self.blocked_turns = 0;
+        }
 
         // move to the best point
         if good_point != self.position {
@@ -101,6 +522,17 @@ impl Person {
             self.move_to(terrain, &good_point);
         } else {
             trace!("I, {}  am staying here : {}", self.id, good_point);
+            self.wait_for_way(terrain);
+        }
+    }
+
+    // the way is taken : unless it swaps, the Person waits and the cell it wanted records a refused move
+    fn wait_for_way(&mut self, terrain: &mut Terrain) {
+        if !self.try_swap(terrain) {
+            if let Some(wanted) = self.wanted_cell(terrain) {
+                terrain.record_refused_move(&wanted);
+            }
+            self.set_state(Lifecycle::Blocked, terrain.get_clock());
         }
     }
 }
@@ -118,6 +550,6 @@ impl cmp::PartialEq for Person {
 
 impl fmt::Display for Person {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Person : {{ id : {}, position : {} }}", self.id, self.position)
+        write!(f, "Person : {{ id : {}, position : {}, state : {} }}", self.id, self.position, self.state)
     }
 }
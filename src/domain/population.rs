@@ -0,0 +1,124 @@
+use super::person::Person;
+
+use std::fmt; // formatting for console display
+use std::str::FromStr;
+
+// ***********
+// AGENT CLASS
+// ***********
+/// A kind of Person : how fast it walks, how long it waits before stepping aside
+/// and how much room it takes (a wheelchair, a cart...).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentClass {
+    pub name: String,
+    /// share of the population, relative to the other classes
+    pub share: f32,
+    /// cells per turn : the integer part is a number of moves,
+    /// the fractional part the probability of one more move
+    pub speed: f32,
+    /// turns a Person waits for its way to clear before accepting a move that does not get it closer
+    pub patience: u8,
+    /// side of the square of cells occupied by the Person
+    pub footprint: u8,
+    /// how much the Person follows the crowd rather than its own goal, from 0 to 1 (see `Herding`)
+    pub panic: f32,
+}
+
+impl AgentClass {
+
+    pub fn new(name: &str, share: f32, speed: f32, patience: u8, footprint: u8) -> AgentClass {
+        AgentClass { name: name.to_string(), share, speed, patience, footprint, panic: 0. }
+    }
+
+    /// The historical Person : one move per turn, never waits, one cell.
+    pub fn walker() -> AgentClass {
+        AgentClass::new("walker", 1., 1., 0, 1)
+    }
+
+    /// Predefined classes : walker, runner, elderly and wheelchair.
+    pub fn builtin(name: &str) -> Option<AgentClass> {
+        match name {
+            "walker" => Some(AgentClass::walker()),
+            "runner" => Some(AgentClass::new("runner", 1., 1.5, 0, 1)),
+            "elderly" => Some(AgentClass::new("elderly", 1., 0.5, 4, 1)),
+            "wheelchair" => Some(AgentClass::new("wheelchair", 1., 0.8, 4, 2)),
+            _ => None,
+        }
+    }
+
+    /// Gives the attributes of the class to a Person.
+    pub fn apply(&self, class: usize, person: &mut Person) {
+        person.class = class;
+        person.speed = self.speed;
+        person.patience = self.patience;
+        person.footprint = self.footprint;
+        person.panic = self.panic;
+    }
+}
+
+/// Parses a class written as `name:share` for a predefined class
+/// or `name:share:speed:patience:footprint`, both optionally followed by `:panic`
+impl FromStr for AgentClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<AgentClass, String> {
+        let fields: Vec<&str> = s.split(':').map(|f| f.trim()).collect();
+        let share = fields.get(1).unwrap_or(&"1").parse::<f32>()
+            .map_err(|e| format!("invalid share in \"{}\" : {}", s, e))?;
+        let panic = match fields.len() {
+            3 | 6 => fields[fields.len() - 1].parse::<f32>().map_err(|e| format!("invalid panic in \"{}\" : {}", s, e))?,
+            _ => 0.,
+        };
+        let class = match fields.len() {
+            1..=3 => AgentClass::builtin(fields[0])
+                .ok_or(format!("unknown class \"{}\", expected walker, runner, elderly, wheelchair or name:share:speed:patience:footprint", fields[0]))?,
+            5 | 6 => AgentClass {
+                name: fields[0].to_string(),
+                share,
+                speed: fields[2].parse::<f32>().map_err(|e| format!("invalid speed in \"{}\" : {}", s, e))?,
+                patience: fields[3].parse::<u8>().map_err(|e| format!("invalid patience in \"{}\" : {}", s, e))?,
+                footprint: fields[4].parse::<u8>().map_err(|e| format!("invalid footprint in \"{}\" : {}", s, e))?,
+                panic,
+            },
+            _ => return Err(format!("\"{}\" is not a class, expected name:share[:panic] or name:share:speed:patience:footprint[:panic]", s)),
+        };
+        if class.footprint == 0 || class.speed < 0. || share < 0. {
+            return Err(format!("\"{}\" : the footprint must be positive, the speed and share not negative", s));
+        }
+        if !(0. ..=1.).contains(&panic) {
+            return Err(format!("\"{}\" : the panic must be between 0 and 1", s));
+        }
+        Ok(AgentClass { share, panic, ..class })
+    }
+}
+
+impl fmt::Display for AgentClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AgentClass : {{ name : {}, share : {}, speed : {}, patience : {}, footprint : {}, panic : {} }}",
+               self.name, self.share, self.speed, self.patience, self.footprint, self.panic)
+    }
+}
+
+/// Parses a population mix : classes separated by commas, e.g. `walker:0.8,wheelchair:0.2`
+pub fn parse_population(s: &str) -> Result<Vec<AgentClass>, String> {
+    s.split(',').map(|c| c.parse::<AgentClass>()).collect()
+}
+
+/// Class of the Person of a given rank (from 0) in a population mix.
+/// The ranks are spread over the classes by a low discrepancy sequence :
+/// any group of consecutive Persons follows the shares of the mix.
+pub fn class_of(rank: usize, classes: &[AgentClass]) -> usize {
+    if classes.len() < 2 {
+        return 0;
+    }
+    let total: f32 = classes.iter().map(|c| c.share).sum();
+    let position = ((rank as f64 + 1.) * 0.618_033_988_749_895).fract() as f32 * total;
+    let mut cumulated = 0.;
+    for (idx, class) in classes.iter().enumerate() {
+        cumulated += class.share;
+        if position < cumulated {
+            return idx;
+        }
+    }
+    classes.len() - 1
+}
@@ -0,0 +1,404 @@
+use super::terrain::Terrain;
+use super::building::Building;
+use super::person::Person;
+use super::population::AgentClass;
+use super::arrivals::Arrivals;
+
+use std::cmp;
+use std::collections::HashMap;
+
+use std::fmt; // formatting for console display
+use std::str::FromStr;
+
+// ******
+// REPORT
+// ******
+/// Outcome of a simulation from the evacuation point of view.
+#[derive(Debug, Clone, Default)]
+pub struct EvacuationReport {
+    pub ticks: u64,
+    pub escaped: usize,
+    pub casualties: usize,
+    pub exposure_ticks: u64,
+    /// cells exchanged by face to face Persons
+    pub swaps: usize,
+    /// Persons that left by each exit of the ground floor
+    pub exit_usage: Vec<usize>,
+    /// averages of a simulation with arrivals, once the steady state is reached
+    pub steady: Option<SteadyStateReport>,
+    /// outcome of the Persons, see `OutcomeReport::from_persons`
+    pub outcome: OutcomeReport,
+}
+
+impl EvacuationReport {
+    pub fn from_terrain(terrain: &Terrain) -> EvacuationReport {
+        EvacuationReport {
+            ticks: terrain.get_tick(),
+            escaped: terrain.get_exited_cnt(),
+            casualties: terrain.get_casualties_cnt(),
+            exposure_ticks: terrain.get_exposure_ticks(),
+            swaps: terrain.get_swaps_cnt(),
+            exit_usage: terrain.get_exit_usage().to_vec(),
+            steady: None,
+            outcome: OutcomeReport::default(),
+        }
+    }
+
+    /// Sums up the floors : escapes only happen on the ground floor,
+    /// the duration is the one of the floor that took the most ticks.
+    pub fn from_building(building: &Building) -> EvacuationReport {
+        let mut report = EvacuationReport::default();
+        for (idx, pterrain) in building.get_floors().iter().enumerate() {
+            let floor = EvacuationReport::from_terrain(&pterrain.lock().unwrap());
+            report.ticks = cmp::max(report.ticks, floor.ticks);
+            if idx == 0 {
+                report.escaped = floor.escaped;
+                report.exit_usage = floor.exit_usage;
+            }
+            report.casualties += floor.casualties;
+            report.exposure_ticks += floor.exposure_ticks;
+            report.swaps += floor.swaps;
+        }
+        report
+    }
+
+    /// Written as its values separated by spaces, the exit usage, the steady state and the lists
+    /// of the outcome as comma separated lists (`-` if there is none), to be sent by the child process of a run.
+    pub fn to_record(&self) -> String {
+        let list = |values: &[usize]| -> String {
+            let values: Vec<String> = values.iter().map(|u| u.to_string()).collect();
+            if values.is_empty() { "-".to_string() } else { values.join(",") }
+        };
+        let steady = match self.steady {
+            Some(s) => format!("{},{},{},{},{},{}", s.ticks, s.warmup, s.mean_occupancy, s.throughput, s.arrived, s.waiting),
+            None => "-".to_string(),
+        };
+        let o = &self.outcome;
+        format!("{} {} {} {} {} {} {} {} {} {} {} {} {}", self.ticks, self.escaped, self.casualties, self.exposure_ticks, self.swaps,
+                list(&self.exit_usage), steady, o.evacuation_ticks, list(&o.escape_curve),
+                o.mean_time_to_escape, o.median_time_to_escape, o.p95_time_to_escape, o.distance)
+    }
+}
+
+impl FromStr for EvacuationReport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EvacuationReport, String> {
+        let values: Vec<&str> = s.split_whitespace().collect();
+        if values.len() != 13 {
+            return Err(format!("\"{}\" is not an EvacuationReport record", s));
+        }
+        let err = |e: &dyn fmt::Display| format!("invalid value in \"{}\" : {}", s, e);
+        let list = |v: &str| -> Vec<String> { if v == "-" { Vec::new() } else { v.split(',').map(|i| i.to_string()).collect() } };
+        let int = |v: &str| v.parse::<u64>().map_err(|e| err(&e));
+        let real = |v: &str| v.parse::<f32>().map_err(|e| err(&e));
+        let counts = |v: &str| list(v).iter().map(|u| int(u).map(|u| u as usize)).collect::<Result<Vec<usize>, String>>();
+        let steady = list(values[6]);
+        Ok(EvacuationReport {
+            ticks: int(values[0])?,
+            escaped: int(values[1])? as usize,
+            casualties: int(values[2])? as usize,
+            exposure_ticks: int(values[3])?,
+            swaps: int(values[4])? as usize,
+            exit_usage: counts(values[5])?,
+            steady: if steady.len() == 6 {
+                Some(SteadyStateReport {
+                    ticks: int(&steady[0])?,
+                    warmup: int(&steady[1])?,
+                    mean_occupancy: real(&steady[2])?,
+                    throughput: real(&steady[3])?,
+                    arrived: int(&steady[4])? as usize,
+                    waiting: int(&steady[5])? as usize,
+                })
+            } else { None },
+            outcome: OutcomeReport {
+                evacuation_ticks: int(values[7])?,
+                escape_curve: counts(values[8])?,
+                mean_time_to_escape: real(values[9])?,
+                median_time_to_escape: real(values[10])?,
+                p95_time_to_escape: real(values[11])?,
+                distance: real(values[12])?,
+            },
+        })
+    }
+}
+
+/// Outcome of a simulation for its Persons. Each Person keeps its own counts while it moves
+/// (no lock shared by the Persons), they are summed up once the Persons are back from their threads.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutcomeReport {
+    /// tick at which the last escaped Person left
+    pub evacuation_ticks: u64,
+    /// Persons escaped at each tick
+    pub escape_curve: Vec<usize>,
+    /// ticks between placement and escape of the escaped Persons
+    pub mean_time_to_escape: f32,
+    pub median_time_to_escape: f32,
+    pub p95_time_to_escape: f32,
+    /// cells walked by all the Persons
+    pub distance: f32,
+}
+
+impl OutcomeReport {
+    pub fn from_persons(persons: &[Person]) -> OutcomeReport {
+        let mut report = OutcomeReport::default();
+        let mut times: Vec<u64> = Vec::new();
+        for pers in persons {
+            report.distance += pers.distance;
+            if !pers.has_escaped() {
+                continue;
+            }
+            let tick = pers.left_tick.unwrap_or(0);
+            report.evacuation_ticks = cmp::max(report.evacuation_ticks, tick);
+            if report.escape_curve.len() <= tick as usize {
+                report.escape_curve.resize(tick as usize + 1, 0);
+            }
+            report.escape_curve[tick as usize] += 1;
+            times.extend(pers.time_to_escape());
+        }
+        if !times.is_empty() {
+            times.sort();
+            report.mean_time_to_escape = times.iter().sum::<u64>() as f32 / times.len() as f32;
+            report.median_time_to_escape = percentile(&times, 50.);
+            report.p95_time_to_escape = percentile(&times, 95.);
+        }
+        report
+    }
+
+    /// Most Persons escaped in a single tick, and the first tick it happened.
+    pub fn peak_flow(&self) -> (usize, u64) {
+        self.escape_curve.iter().enumerate()
+            .fold((0, 0), |peak, (tick, &cnt)| if cnt > peak.0 { (cnt, tick as u64) } else { peak })
+    }
+}
+
+// percentile of sorted values, interpolated between the two closest ranks
+fn percentile(sorted: &[u64], p: f32) -> f32 {
+    let rank = p / 100. * (sorted.len() - 1) as f32;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] as f32 + (sorted[high] as f32 - sorted[low] as f32) * (rank - low as f32)
+}
+
+impl fmt::Display for OutcomeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (peak, peak_tick) = self.peak_flow();
+        write!(f, "OutcomeReport : {{ evacuation : {} ticks, time to escape : mean {:.1}, median {:.1}, p95 {:.1} ticks, peak flow : {} at tick {}, distance : {:.1} cells }}",
+               self.evacuation_ticks, self.mean_time_to_escape, self.median_time_to_escape,
+               self.p95_time_to_escape, peak, peak_tick, self.distance)
+    }
+}
+
+/// Outcome of a simulation for one class of Persons.
+#[derive(Debug, Clone, Default)]
+pub struct ClassReport {
+    pub name: String,
+    pub persons: usize,
+    pub escaped: usize,
+    pub casualties: usize,
+    /// mean and last ticks at which the escaped Persons of the class left
+    pub mean_exit_tick: f32,
+    pub last_exit_tick: u64,
+    /// mean number of turns spent on hazard cells
+    pub mean_exposure: f32,
+    /// mean ticks between placement and escape of the escaped Persons
+    pub mean_time_to_escape: f32,
+    /// mean ticks spent blocked and mean number of moves of the Persons of the class
+    pub mean_blocked_ticks: f32,
+    pub mean_moves: f32,
+}
+
+impl ClassReport {
+    /// One report per class of the population, built from the Persons at the end of a simulation.
+    pub fn by_class(persons: &[Person], classes: &[AgentClass]) -> Vec<ClassReport> {
+        let mut reports: Vec<ClassReport> = classes.iter()
+            .map(|c| ClassReport { name: c.name.clone(), ..ClassReport::default() })
+            .collect();
+        let mut exposures: Vec<u64> = vec![0; classes.len()];
+        for pers in persons {
+            let report = &mut reports[pers.class];
+            report.persons += 1;
+            exposures[pers.class] += pers.exposure_turns;
+            report.mean_blocked_ticks += pers.blocked_ticks as f32;
+            report.mean_moves += pers.moves_cnt as f32;
+            if pers.is_casualty() {
+                report.casualties += 1;
+            } else if pers.has_escaped() {
+                let tick = pers.left_tick.unwrap_or(0);
+                report.escaped += 1;
+                report.mean_exit_tick += tick as f32;
+                report.mean_time_to_escape += pers.time_to_escape().unwrap_or(0) as f32;
+                report.last_exit_tick = cmp::max(report.last_exit_tick, tick);
+            }
+        }
+        for (report, exposure) in reports.iter_mut().zip(exposures) {
+            if report.escaped > 0 {
+                report.mean_exit_tick /= report.escaped as f32;
+                report.mean_time_to_escape /= report.escaped as f32;
+            }
+            if report.persons > 0 {
+                report.mean_exposure = exposure as f32 / report.persons as f32;
+                report.mean_blocked_ticks /= report.persons as f32;
+                report.mean_moves /= report.persons as f32;
+            }
+        }
+        reports
+    }
+}
+
+impl fmt::Display for ClassReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ClassReport : {{ class : {}, persons : {}, escaped : {}, casualties : {}, mean exit tick : {:.1}, last exit tick : {}, mean exposure : {:.1} turns, mean time to escape : {:.1}, mean blocked : {:.1} ticks, mean moves : {:.1} }}",
+               self.name, self.persons, self.escaped, self.casualties, self.mean_exit_tick, self.last_exit_tick, self.mean_exposure,
+               self.mean_time_to_escape, self.mean_blocked_ticks, self.mean_moves)
+    }
+}
+
+/// Outcome of a simulation for the groups of Persons : a group is evacuated
+/// when its last member left, its spread is the time between its first and last members.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct GroupReport {
+    pub groups: usize,
+    /// groups whose members all escaped
+    pub evacuated: usize,
+    pub mean_evacuation_tick: f32,
+    pub last_evacuation_tick: u64,
+    pub mean_spread: f32,
+}
+
+impl GroupReport {
+    pub fn from_persons(persons: &[Person]) -> GroupReport {
+        // per group : first and last exit ticks, and whether every member escaped
+        let mut groups: HashMap<usize, (u64, u64, bool)> = HashMap::new();
+        for pers in persons {
+            if let Some(group) = pers.group {
+                let tick = pers.left_tick.unwrap_or(0);
+                let entry = groups.entry(group).or_insert((tick, tick, true));
+                entry.0 = cmp::min(entry.0, tick);
+                entry.1 = cmp::max(entry.1, tick);
+                entry.2 = entry.2 && pers.has_escaped();
+            }
+        }
+
+        let mut report = GroupReport { groups: groups.len(), ..GroupReport::default() };
+        for &(first, last, escaped) in groups.values() {
+            if escaped {
+                report.evacuated += 1;
+                report.mean_evacuation_tick += last as f32;
+                report.last_evacuation_tick = cmp::max(report.last_evacuation_tick, last);
+                report.mean_spread += (last - first) as f32;
+            }
+        }
+        if report.evacuated > 0 {
+            report.mean_evacuation_tick /= report.evacuated as f32;
+            report.mean_spread /= report.evacuated as f32;
+        }
+        report
+    }
+}
+
+impl fmt::Display for GroupReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GroupReport : {{ groups : {}, evacuated : {}, mean evacuation tick : {:.1}, last evacuation tick : {}, mean spread : {:.1} ticks }}",
+               self.groups, self.evacuated, self.mean_evacuation_tick, self.last_evacuation_tick, self.mean_spread)
+    }
+}
+
+/// Steady state of a simulation with arrivals : the occupancy (Persons in the Building)
+/// and the throughput (Persons escaped per tick) averaged after the warm-up ticks.
+/// Without a given warm-up, the steady state starts once the occupancy first reaches
+/// the mean occupancy of the second half of the run.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SteadyStateReport {
+    pub ticks: u64,
+    pub warmup: u64,
+    pub mean_occupancy: f32,
+    pub throughput: f32,
+    /// Persons that arrived, and Persons due still waiting for room in their source
+    pub arrived: usize,
+    pub waiting: usize,
+}
+
+impl SteadyStateReport {
+    pub fn from_building(building: &Building, warmup: Option<u64>, arrivals: &Arrivals) -> SteadyStateReport {
+        // occupancy of the Building at the end of each tick of the ground floor
+        let mut occupancy: Vec<usize> = Vec::new();
+        let mut escaped: Vec<usize> = Vec::new();
+        for (idx, pterrain) in building.get_floors().iter().enumerate() {
+            let terrain = pterrain.lock().unwrap();
+            let history = terrain.get_history();
+            if idx == 0 {
+                occupancy = history.iter().map(|&(active, _)| active).collect();
+                escaped = history.iter().map(|&(_, exited)| exited).collect();
+            } else {
+                for (occ, &(active, _)) in occupancy.iter_mut().zip(history) {
+                    *occ += active;
+                }
+            }
+        }
+
+        let ticks = occupancy.len();
+        let warmup = match warmup {
+            Some(w) => cmp::min(w as usize, ticks),
+            None if ticks > 0 => {
+                let second_half = &occupancy[ticks / 2..];
+                let level = second_half.iter().sum::<usize>() as f32 / second_half.len() as f32;
+                occupancy.iter().position(|&occ| occ as f32 >= level).unwrap_or(0)
+            }
+            None => 0,
+        };
+        let mut report = SteadyStateReport {
+            ticks: ticks as u64,
+            warmup: warmup as u64,
+            arrived: arrivals.arrived,
+            waiting: arrivals.get_waiting_cnt(),
+            ..SteadyStateReport::default()
+        };
+        if warmup < ticks {
+            let steady = (ticks - warmup) as f32;
+            report.mean_occupancy = occupancy[warmup..].iter().sum::<usize>() as f32 / steady;
+            let before = if warmup == 0 { 0 } else { escaped[warmup - 1] };
+            report.throughput = (escaped[ticks - 1] - before) as f32 / steady;
+        }
+        report
+    }
+}
+
+impl fmt::Display for SteadyStateReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SteadyStateReport : {{ ticks : {}, warm-up : {} ticks, mean occupancy : {:.1}, throughput : {:.3} per tick, arrived : {}, waiting : {} }}",
+               self.ticks, self.warmup, self.mean_occupancy, self.throughput, self.arrived, self.waiting)
+    }
+}
+
+/// How evenly the Persons used the exits : the imbalance is the difference between
+/// the most and the least used exits, as a share of the escaped Persons
+/// (0 : all the exits were used as much, 1 : a single exit was used).
+#[derive(Debug, Clone, Default)]
+pub struct ExitReport {
+    pub usage: Vec<usize>,
+    pub imbalance: f32,
+}
+
+impl ExitReport {
+    pub fn from_usage(usage: &[usize]) -> ExitReport {
+        let escaped: usize = usage.iter().sum();
+        let imbalance = match (usage.iter().max(), usage.iter().min()) {
+            (Some(max), Some(min)) if escaped > 0 => (max - min) as f32 / escaped as f32,
+            _ => 0.,
+        };
+        ExitReport { usage: usage.to_vec(), imbalance }
+    }
+}
+
+impl fmt::Display for ExitReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ExitReport : {{ usage : {:?}, imbalance : {:.2} }}", self.usage, self.imbalance)
+    }
+}
+
+impl fmt::Display for EvacuationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EvacuationReport : {{ ticks : {}, escaped : {}, casualties : {}, exposure : {} ticks, swaps : {} }}",
+               self.ticks, self.escaped, self.casualties, self.exposure_ticks, self.swaps)
+    }
+}
@@ -0,0 +1,92 @@
+use std::mem;
+
+/// Side of the square chunks of a `ChunkedGrid`, in cells.
+pub const CHUNK_SIZE: usize = 32;
+
+// ************
+// CHUNKED GRID
+// ************
+/// A grid of cells stored by square chunks of `CHUNK_SIZE` x `CHUNK_SIZE` cells.
+/// A chunk is only allocated once one of its cells holds a value other than the default one,
+/// so that large and mostly empty grids stay small in memory.
+#[derive(Debug, Clone)]
+pub struct ChunkedGrid<T> {
+    xsize: usize,
+    ysize: usize,
+    xchunks: usize, // number of chunks on the x axis
+    default: T,
+    chunks: Vec<Option<Box<[T]>>>,
+}
+
+impl<T: Copy + PartialEq> ChunkedGrid<T> {
+
+    pub fn new(xsize: usize, ysize: usize, default: T) -> ChunkedGrid<T> {
+        let xchunks = xsize.div_ceil(CHUNK_SIZE);
+        let ychunks = ysize.div_ceil(CHUNK_SIZE);
+        ChunkedGrid {
+            xsize,
+            ysize,
+            xchunks,
+            default,
+            chunks: (0..xchunks * ychunks).map(|_| None).collect(),
+        }
+    }
+
+    /// Index of the chunk holding the cell (x, y).
+    pub fn chunk_index(&self, x: usize, y: usize) -> usize {
+        (y / CHUNK_SIZE) * self.xchunks + x / CHUNK_SIZE
+    }
+
+    // offset of the cell (x, y) inside its chunk
+    fn offset_in_chunk(x: usize, y: usize) -> usize {
+        (y % CHUNK_SIZE) * CHUNK_SIZE + x % CHUNK_SIZE
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> T {
+        assert!(x < self.xsize && y < self.ysize, "({}, {}) is out of the grid", x, y);
+        match self.chunks[self.chunk_index(x, y)] {
+            Some(ref chunk) => chunk[ChunkedGrid::<T>::offset_in_chunk(x, y)],
+            None => self.default,
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: T) {
+        assert!(x < self.xsize && y < self.ysize, "({}, {}) is out of the grid", x, y);
+        let idx = self.chunk_index(x, y);
+        if self.chunks[idx].is_none() {
+            if value == self.default {
+                return; // nothing to store
+            }
+            self.chunks[idx] = Some(vec![self.default; CHUNK_SIZE * CHUNK_SIZE].into_boxed_slice());
+        }
+        if let Some(ref mut chunk) = self.chunks[idx] {
+            chunk[ChunkedGrid::<T>::offset_in_chunk(x, y)] = value;
+        }
+    }
+
+    /// Calls `f(x, y, value)` for every cell that does not hold the default value.
+    pub fn for_each_set<F: FnMut(usize, usize, T)>(&self, mut f: F) {
+        for (idx, chunk) in self.chunks.iter().enumerate() {
+            if let Some(ref chunk) = *chunk {
+                let x0 = (idx % self.xchunks) * CHUNK_SIZE;
+                let y0 = (idx / self.xchunks) * CHUNK_SIZE;
+                for (offset, value) in chunk.iter().enumerate() {
+                    let (x, y) = (x0 + offset % CHUNK_SIZE, y0 + offset / CHUNK_SIZE);
+                    if *value != self.default && x < self.xsize && y < self.ysize {
+                        f(x, y, *value);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn get_allocated_chunks(&self) -> usize {
+        self.chunks.iter().filter(|c| c.is_some()).count()
+    }
+
+    /// Approximate memory used by the grid, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.chunks.len() * mem::size_of::<Option<Box<[T]>>>()
+            + self.get_allocated_chunks() * CHUNK_SIZE * CHUNK_SIZE * mem::size_of::<T>()
+    }
+}
@@ -0,0 +1,319 @@
+extern crate rand;
+
+use super::Point;
+use super::terrain::Terrain;
+use super::person::Person;
+
+use std::cmp::Ordering;
+use std::f32;
+use std::fmt; // formatting for console display
+use std::str::FromStr;
+use std::sync::Arc;
+use self::rand::Rng;
+
+/// Added to the score of a cell the Person recently left : it goes back only if there is no other way.
+pub const MEMORY_PENALTY: f32 = 2.5;
+
+/// Scores closer than this are ties.
+const TIE_EPSILON: f32 = 1e-4;
+
+/// Distance up to which a herding Person watches its neighbours (unless its perception is shorter).
+pub const HERD_RADIUS: f32 = 5.;
+
+// ********
+// STRATEGY
+// ********
+/// What a Person decides to do during its turn.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Decision {
+    MoveTo(Point),
+    Stay,
+}
+
+/// The way a Person chooses its moves.
+/// A strategy is shared by any number of Persons : the state specific to a Person
+/// (position, random generator...) is read from the Person itself.
+pub trait MovementStrategy: fmt::Debug + Send + Sync {
+
+    fn name(&self) -> &'static str;
+
+    /// Score of a candidate move for a Person, the lower the better.
+    fn score(&self, person: &Person, candidate: &Point, terrain: &Terrain) -> f32;
+
+    /// Chooses among the candidate moves (free neighbour cells) :
+    /// by default, the best scored one once the hazards and the recently visited cells are taken into account.
+    fn decide(&self, person: &Person, moves: &[Point], terrain: &Terrain) -> Decision {
+        decide_by_score(self, person, moves, terrain)
+    }
+}
+
+/// The default `decide` of the strategies : the best scored move, hazards and recently visited cells included.
+pub fn decide_by_score<S: MovementStrategy + ?Sized>(strategy: &S, person: &Person, moves: &[Point], terrain: &Terrain) -> Decision {
+    let moves_and_scores: Vec<(Point, f32)> = moves.iter()
+        .map(|m| (*m, strategy.score(person, m, terrain) + hazard_penalty(person, m, terrain) + memory_penalty(person, m)))
+        .collect();
+    debug!("debug sort :{:?}", moves_and_scores); // debug
+
+    match pick_best(person, &moves_and_scores) {
+        Some(point) => Decision::MoveTo(point),
+        None => Decision::Stay,
+    }
+}
+
+/// The best scored move. With a memory (see `Person::memory_size`), ties are broken at random
+/// with the generator of the Person (so that two Persons in the same situation do not always go the same way),
+/// otherwise they go to the first move.
+pub fn pick_best(person: &Person, moves_and_scores: &[(Point, f32)]) -> Option<Point> {
+    let best = moves_and_scores.iter().fold(f32::INFINITY, |best, &(_, score)| best.min(score));
+    let ties: Vec<Point> = moves_and_scores.iter()
+        .filter(|&&(_, score)| score <= best + TIE_EPSILON)
+        .map(|&(point, _)| point)
+        .collect();
+    match ties.len() {
+        0 => None,
+        1 => Some(ties[0]),
+        _ if person.memory_size == 0 => Some(ties[0]),
+        n => Some(ties[person.rng.borrow_mut().gen_range(0, n)]),
+    }
+}
+
+/// Persons avoid going back to the cells they just left (bouncing between the same cells).
+pub fn memory_penalty(person: &Person, candidate: &Point) -> f32 {
+    if person.remembers(candidate) { MEMORY_PENALTY } else { 0. }
+}
+
+/// Score of a move towards a target : the distance gained per turn spent on the candidate cell,
+/// so that a slow cell is worth taking only if it is a shortcut.
+pub fn progress_score(person: &Person, candidate: &Point, target: &Point, terrain: &Terrain) -> f32 {
+    let gain = person.position.distance_to(target) - candidate.distance_to(target);
+    let cost = terrain.get_traversal_cost(candidate) as f32;
+    // lower is better : moving away from the target is even worse on a slow cell
+    if gain > 0. { -gain / cost } else { -gain * cost }
+}
+
+/// Persons averse to hazards keep away from cells close to a hazard.
+pub fn hazard_penalty(person: &Person, candidate: &Point, terrain: &Terrain) -> f32 {
+    if person.hazard_aversion == 0. {
+        0.
+    } else {
+        person.hazard_aversion * terrain.get_hazard_around(candidate) as f32 / 255.
+    }
+}
+
+/// Heads to the azimuth of the Terrain (the historical behaviour).
+#[derive(Debug, Copy, Clone)]
+pub struct GreedyToAzimuth;
+
+impl MovementStrategy for GreedyToAzimuth {
+    fn name(&self) -> &'static str { "azimuth" }
+
+    fn score(&self, person: &Person, candidate: &Point, terrain: &Terrain) -> f32 {
+        let target = person.heading().unwrap_or(*terrain.get_azimuth());
+        progress_score(person, candidate, &target, terrain)
+    }
+
+    /// Without a memory, the historical moves : the first of the moves closest to the target by squared distance.
+    /// With one, the default `decide` (slow cells, hazards, recently visited cells and random ties).
+    fn decide(&self, person: &Person, moves: &[Point], terrain: &Terrain) -> Decision {
+        if person.memory_size > 0 {
+            return decide_by_score(self, person, moves, terrain);
+        }
+        let target = person.heading().unwrap_or(*terrain.get_azimuth());
+        let mut moves_and_dist: Vec<(Point, f32)> = moves.iter()
+            .map(|m| (*m, m.square_distance_to(&target)))
+            .collect();
+        moves_and_dist.sort_by(|x, y| x.1.partial_cmp(&y.1).unwrap_or(Ordering::Equal));
+        debug!("debug sort :{:?}", moves_and_dist); // debug
+
+        match moves_and_dist.first() {
+            Some(&(point, _)) => Decision::MoveTo(point),
+            None => Decision::Stay,
+        }
+    }
+}
+
+/// Heads in straight line to the exit the Person chose (see `Person::target_exit`).
+#[derive(Debug, Copy, Clone)]
+pub struct GreedyToTargetExit;
+
+impl MovementStrategy for GreedyToTargetExit {
+    fn name(&self) -> &'static str { "exit" }
+
+    fn score(&self, person: &Person, candidate: &Point, terrain: &Terrain) -> f32 {
+        progress_score(person, candidate, &exit_target(person, terrain), terrain)
+    }
+}
+
+/// The point a Person heads to : what it saw if its perception is limited,
+/// otherwise the nearest cell of the exit it chose (or the azimuth if the Terrain has no exit).
+pub fn exit_target(person: &Person, terrain: &Terrain) -> Point {
+    match (person.heading(), person.target_exit(terrain)) {
+        (Some(heading), _) => heading,
+        (None, Some(exit)) => exit.nearest_cell(&person.position),
+        (None, None) => *terrain.get_azimuth(),
+    }
+}
+
+/// Heads to the exit of the Person (see `exit_target`), drawn by the way its neighbours walk :
+/// the more panicked the Person, the more it follows the crowd rather than its own goal.
+#[derive(Debug, Copy, Clone)]
+pub struct Herding;
+
+impl Herding {
+    // the blend of the goal direction and the mean last move of the visible neighbours
+    fn direction(person: &Person, terrain: &Terrain) -> (f32, f32) {
+        let target = exit_target(person, terrain);
+        let goal = unit(target.x - person.position.x, target.y - person.position.y);
+        if person.panic == 0. {
+            return goal;
+        }
+        let radius = person.knowledge.as_ref().map_or(HERD_RADIUS, |k| k.radius.min(HERD_RADIUS));
+        let range = radius as isize;
+        let own = person.id as isize;
+        let (mut dx, mut dy) = (0, 0);
+        for x in person.position.x - range..person.position.x + range + 1 {
+            for y in person.position.y - range..person.position.y + range + 1 {
+                let cell = Point{x, y};
+                if !terrain.is_in_bounds(&cell) || cell.distance_to(&person.position) > radius {
+                    continue;
+                }
+                let id = terrain.get_pt_val(&cell);
+                if id <= 0 || id == own || !terrain.is_visible(&person.position, &cell) {
+                    continue;
+                }
+                if let Some(last) = terrain.get_last_move(id) {
+                    // a large Person is counted once per cell : it draws the crowd more
+                    dx += last.x;
+                    dy += last.y;
+                }
+            }
+        }
+        if dx == 0 && dy == 0 {
+            return goal;
+        }
+        let crowd = unit(dx, dy);
+        (goal.0 * (1. - person.panic) + crowd.0 * person.panic,
+         goal.1 * (1. - person.panic) + crowd.1 * person.panic)
+    }
+}
+
+// the vector of length 1 in the direction (0 for a null vector)
+fn unit(dx: isize, dy: isize) -> (f32, f32) {
+    let norm = ((dx * dx + dy * dy) as f32).sqrt();
+    if norm == 0. { (0., 0.) } else { (dx as f32 / norm, dy as f32 / norm) }
+}
+
+// lower is better : the opposite of the progress made in the direction
+fn direction_score(person: &Person, candidate: &Point, direction: (f32, f32)) -> f32 {
+    let step = unit(candidate.x - person.position.x, candidate.y - person.position.y);
+    -(step.0 * direction.0 + step.1 * direction.1)
+}
+
+impl MovementStrategy for Herding {
+    fn name(&self) -> &'static str { "herd" }
+
+    fn score(&self, person: &Person, candidate: &Point, terrain: &Terrain) -> f32 {
+        direction_score(person, candidate, Herding::direction(person, terrain))
+    }
+
+    /// Same as the default, with the direction computed once for all the moves.
+    fn decide(&self, person: &Person, moves: &[Point], terrain: &Terrain) -> Decision {
+        let direction = Herding::direction(person, terrain);
+        let moves_and_scores: Vec<(Point, f32)> = moves.iter()
+            .map(|m| (*m, direction_score(person, m, direction) + hazard_penalty(person, m, terrain) + memory_penalty(person, m)))
+            .collect();
+        match pick_best(person, &moves_and_scores) {
+            Some(point) => Decision::MoveTo(point),
+            None => Decision::Stay,
+        }
+    }
+}
+
+/// Moves to any free neighbour cell, at random.
+#[derive(Debug, Copy, Clone)]
+pub struct RandomWalk;
+
+impl MovementStrategy for RandomWalk {
+    fn name(&self) -> &'static str { "random" }
+
+    fn score(&self, person: &Person, _candidate: &Point, _terrain: &Terrain) -> f32 {
+        person.rng.borrow_mut().next_f32()
+    }
+
+    fn decide(&self, person: &Person, moves: &[Point], _terrain: &Terrain) -> Decision {
+        match person.rng.borrow_mut().choose(moves) {
+            Some(point) => Decision::MoveTo(*point),
+            None => Decision::Stay,
+        }
+    }
+}
+
+/// Follows the distance field of the Terrain : the shortest path to an exit,
+/// around obstacles and slow cells.
+#[derive(Debug, Copy, Clone)]
+pub struct FieldFollowing;
+
+impl MovementStrategy for FieldFollowing {
+    fn name(&self) -> &'static str { "field" }
+
+    fn score(&self, person: &Person, candidate: &Point, terrain: &Terrain) -> f32 {
+        match person.heading() {
+            // the field is not known : the Person heads to what it saw
+            Some(heading) => candidate.distance_to(&heading),
+            None => terrain.get_footprint_exit_distance(candidate, person.footprint as usize),
+        }
+    }
+
+    /// Waits rather than stepping back when the way to the exit is jammed.
+    /// The current cell bears the same hazard penalty as the moves : a Person next to a hazard moves away from it.
+    fn decide(&self, person: &Person, moves: &[Point], terrain: &Terrain) -> Decision {
+        let here = self.score(person, &person.position, terrain) + hazard_penalty(person, &person.position, terrain);
+        let closer: Vec<(Point, f32)> = moves.iter()
+            .map(|m| (*m, self.score(person, m, terrain) + hazard_penalty(person, m, terrain)))
+            .filter(|&(_, score)| score < here)
+            .collect();
+        match pick_best(person, &closer) {
+            Some(point) => Decision::MoveTo(point),
+            None => Decision::Stay,
+        }
+    }
+}
+
+/// The built-in strategies, to choose them from the command line.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum StrategyKind {
+    #[default]
+    GreedyToAzimuth,
+    GreedyToTargetExit,
+    RandomWalk,
+    FieldFollowing,
+    Herding,
+}
+
+impl StrategyKind {
+    pub fn build(&self) -> Arc<dyn MovementStrategy> {
+        match *self {
+            StrategyKind::GreedyToAzimuth => Arc::new(GreedyToAzimuth),
+            StrategyKind::GreedyToTargetExit => Arc::new(GreedyToTargetExit),
+            StrategyKind::RandomWalk => Arc::new(RandomWalk),
+            StrategyKind::FieldFollowing => Arc::new(FieldFollowing),
+            StrategyKind::Herding => Arc::new(Herding),
+        }
+    }
+}
+
+/// Parses the name of a strategy : azimuth, exit, random, field or herd
+impl FromStr for StrategyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<StrategyKind, String> {
+        match s {
+            "azimuth" => Ok(StrategyKind::GreedyToAzimuth),
+            "exit" => Ok(StrategyKind::GreedyToTargetExit),
+            "random" => Ok(StrategyKind::RandomWalk),
+            "field" => Ok(StrategyKind::FieldFollowing),
+            "herd" => Ok(StrategyKind::Herding),
+            _ => Err(format!("unknown strategy \"{}\", expected azimuth, exit, random, field or herd", s)),
+        }
+    }
+}
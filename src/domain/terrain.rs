@@ -1,47 +1,128 @@
 extern crate rand;
 
-use super::YSIZE;
-use super::XSIZE;
-use super::NBEXIT;
 use super::Point;
+use super::storage::ChunkedGrid;
+use super::field::DistanceField;
+use super::exit::Exit;
+use super::events::{LayoutEvent, ScheduledEvent, parse_timeline};
+use super::hazard::HazardRules;
+use super::perception::Sign;
 
 use std::fmt; // formatting for console display
+use std::cell::RefCell;
+use std::cmp;
+use std::f32;
+use std::mem;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use self::rand::{Rng, SeedableRng};
 
+/// Cost of a normal cell : a Person standing on it can move at every turn.
+pub const DEFAULT_COST: u8 = 1;
+/// Seed of the random generators of a Terrain, unless another one is set.
+pub const DEFAULT_SEED: usize = 1;
+
 // *******
 // TERRAIN
 // *******
 pub struct Terrain {
     pub xsize : usize,
     pub ysize : usize,
-    data : Vec<isize>,
-    exit_points : [Point; NBEXIT],
+    data : ChunkedGrid<i32>, // 0 : free, -1 : obstacle, > 0 : id of the Person
+    row_occupation : Vec<usize>, // number of cells of each row that are not free
+    costs : ChunkedGrid<u8>,
+    hazard : ChunkedGrid<u8>,
+    hazard_cells : Vec<usize>, // offsets of the cells with a hazard
+    hazard_rules : HazardRules,
+    hazard_rng : rand::StdRng,
+    seed : usize, // seed of the random placements and of the hazard spreading
+    exit_points : Vec<Point>,
+    exits : Vec<Exit>, // exit points grouped by adjacency
+    azimuth : Point, // point the Persons head to
     exited_cnt : usize,
+    stair_exits : bool, // the exits lead to stairs down to another floor of a Building
+    stair_cnt : usize, // Persons that left by a stair
+    casualties_cnt : usize,
+    exposure_ticks : u64,
+    placed_cnt : usize,
+    tick : u64,
+    clock : Arc<AtomicUsize>, // most advanced tick of the floors sharing it (see `share_clock`)
+    turns_in_tick : usize,
+    timeline : VecDeque<ScheduledEvent>, // sorted by tick
+    layout_version : u64,
+    exit_fields : RefCell<HashMap<usize, DistanceField>>, // per footprint, computed when needed
+    groups : HashMap<usize, Vec<(usize, Point)>>, // ids and positions of the group members on the Terrain
+    intents : HashMap<usize, (Point, Point)>, // cells of the blocked Persons and the occupied cells they want
+    swapped : HashMap<usize, Point>, // new cells of the Persons moved by a swap, until they notice it
+    swaps_cnt : usize,
+    signs : Vec<Sign>,
+    last_moves : HashMap<isize, Point>, // last move (from its previous cell) of each Person on the Terrain
+    exit_usage : Vec<usize>, // Persons that left by each exit
+    history : Vec<(usize, usize)>, // Persons on the Terrain and Persons escaped so far, at the end of each tick
+    occupied_ticks : ChunkedGrid<u32>, // ticks each cell was occupied, until the tick its current Person came in
+    occupied_since : ChunkedGrid<u32>, // tick the current Person of each cell came in
+    refused_moves : ChunkedGrid<u32>, // moves into each cell refused because it was taken
 }
 
 impl Terrain {
 
-    pub fn get_data_ref(&self) -> &Vec<isize> {
-        &self.data
-    }
-
     // constructor
     pub fn new(xsize: usize, ysize:usize)-> Terrain {
-        let data : Vec<isize> = vec![0; xsize * ysize];//Vec::with_capacity(xsize * ysize);
-
-        Terrain{ xsize, ysize, data,
-        exit_points : Terrain::create_exit_points(),
-        exited_cnt : 0 }
+        // layers are stored by chunks, allocated only where they differ from their default value
+        let data : ChunkedGrid<i32> = ChunkedGrid::new(xsize, ysize, 0);
+        let costs : ChunkedGrid<u8> = ChunkedGrid::new(xsize, ysize, DEFAULT_COST);
+        let hazard : ChunkedGrid<u8> = ChunkedGrid::new(xsize, ysize, 0);
+        let occupied_ticks : ChunkedGrid<u32> = ChunkedGrid::new(xsize, ysize, 0);
+        let occupied_since : ChunkedGrid<u32> = ChunkedGrid::new(xsize, ysize, 0);
+        let refused_moves : ChunkedGrid<u32> = ChunkedGrid::new(xsize, ysize, 0);
+        let seed: &[_] = &[DEFAULT_SEED,]; // constant seed to get consistant hazard spreading between executions.
+        let exit_points = Terrain::create_exit_points(ysize);
+        let exits = Exit::group(&exit_points);
+
+        Terrain{ xsize, ysize, data, costs, hazard, occupied_ticks, occupied_since, refused_moves,
+        row_occupation : vec![0; ysize],
+        hazard_cells : Vec::new(),
+        hazard_rules : HazardRules::default(),
+        hazard_rng : rand::StdRng::from_seed(seed),
+        seed : DEFAULT_SEED,
+        exit_usage : vec![0; exits.len()],
+        exits,
+        exit_points,
+        azimuth : Point{x: -2, y: ysize as isize + 2},
+        exited_cnt : 0,
+        stair_exits : false,
+        stair_cnt : 0,
+        casualties_cnt : 0,
+        exposure_ticks : 0,
+        placed_cnt : 0,
+        tick : 0,
+        clock : Arc::new(AtomicUsize::new(0)),
+        turns_in_tick : 0,
+        timeline : VecDeque::new(),
+        layout_version : 0,
+        exit_fields : RefCell::new(HashMap::new()),
+        groups : HashMap::new(),
+        intents : HashMap::new(),
+        swapped : HashMap::new(),
+        swaps_cnt : 0,
+        signs : Vec::new(),
+        last_moves : HashMap::new(),
+        history : Vec::new() }
     }
 
     /// Creates a sample terrain with premade obstacles
-    /// if YSIZE < 4 or XSIZE < 11 this function fails
+    /// if ysize < 4 or xsize < 11 this function fails
     pub fn new_sample(xsize: usize, ysize:usize) -> Terrain {
         let mut terr = Terrain::new(xsize, ysize);
-        let large_ll = Point{x: XSIZE as isize / 10 , y: 1};
-        let large_ur = Point{x: XSIZE as isize / 10 * 2 , y: YSIZE as isize - 2};
-        let long_ll  = Point{x: XSIZE as isize / 10 * 2 + 2 , y: YSIZE as isize / 5};
-        let long_ur  = Point{x: XSIZE as isize / 10 * 9 -  1 , y: YSIZE as isize / 5 + 1};
+        let large_ll = Point{x: xsize as isize / 10 , y: 1};
+        let large_ur = Point{x: xsize as isize / 10 * 2 , y: ysize as isize - 2};
+        let long_ll  = Point{x: xsize as isize / 10 * 2 + 2 , y: ysize as isize / 5};
+        let long_ur  = Point{x: xsize as isize / 10 * 9 -  1 , y: ysize as isize / 5 + 1};
         //println!("large_lb {},  lagre_rt {} ; long_lb {}, long_rt {}", large_lb, lagre_rt, long_lb, long_rt); // debug
         terr.add_obstacle(large_ll, large_ur); // large obstacle (takes lots of Y)
         terr.add_obstacle(long_ll, long_ur); // long obstacle (takes lot of X)
@@ -49,20 +130,93 @@ impl Terrain {
     }
 
     // constructor helper to create the exit
-    fn create_exit_points() -> [Point; NBEXIT] {
+    fn create_exit_points(ysize: usize) -> Vec<Point> {
         // top left corner
-        [
-            Point{x:0, y: (YSIZE as isize) - 1 },
-            Point{x:0, y: (YSIZE as isize) - 2 },
-            Point{x:1, y: (YSIZE as isize) - 1 },
-            Point{x:1, y: (YSIZE as isize) - 2 }
+        vec![
+            Point{x:0, y: (ysize as isize) - 1 },
+            Point{x:0, y: (ysize as isize) - 2 },
+            Point{x:1, y: (ysize as isize) - 1 },
+            Point{x:1, y: (ysize as isize) - 2 }
         ]
     }
 
-    pub fn get_exit_points(&self) -> &[Point; NBEXIT] {
+    pub fn get_exit_points(&self) -> &[Point] {
         &self.exit_points
     }
 
+    pub fn get_exits(&self) -> &[Exit] {
+        &self.exits
+    }
+
+    /// Removes every exit point (upper floors of a Building are only left by stairs).
+    pub fn clear_exit_points(&mut self) {
+        self.exit_points.clear();
+        self.exits.clear();
+        self.exit_usage.clear();
+        self.layout_version += 1;
+    }
+
+    /// The exits of the Terrain lead to stairs : the Persons leaving by them are still in the Building.
+    pub fn set_stair_exits(&mut self) {
+        self.stair_exits = true;
+    }
+
+    pub fn has_stair_exits(&self) -> bool {
+        self.stair_exits
+    }
+
+    /// Persons moving to an exit point leave the Terrain.
+    /// A point next to an existing exit widens it, otherwise it is a new exit.
+    pub fn add_exit_point(&mut self, point: Point) {
+        if !self.exit_points.contains(&point) {
+            self.exit_points.push(point);
+            self.exits = Exit::group(&self.exit_points);
+            self.exit_usage.resize(self.exits.len(), 0);
+            self.layout_version += 1;
+        }
+    }
+
+    /// Adds a signage cell, seen by the Persons with a limited perception.
+    pub fn add_sign(&mut self, sign: Sign) {
+        self.signs.push(sign);
+    }
+
+    pub fn get_signs(&self) -> &[Sign] {
+        &self.signs
+    }
+
+    /// true if no obstacle stands on the straight line between two points (Persons do not hide the view).
+    pub fn is_visible(&self, from: &Point, to: &Point) -> bool {
+        let steps = cmp::max((to.x - from.x).abs(), (to.y - from.y).abs());
+        (1..steps).all(|i| {
+            let cell = Point {
+                x: from.x + ((to.x - from.x) as f32 * i as f32 / steps as f32).round() as isize,
+                y: from.y + ((to.y - from.y) as f32 * i as f32 / steps as f32).round() as isize,
+            };
+            !self.is_in_bounds(&cell) || self.get_pt_val(&cell) != -1
+        })
+    }
+
+    pub fn get_azimuth(&self) -> &Point {
+        &self.azimuth
+    }
+
+    /// Seeds the random placements and the hazard spreading,
+    /// runs with the same seed place the Persons on the same cells.
+    pub fn set_seed(&mut self, seed: usize) {
+        self.seed = seed;
+        self.hazard_rng = rand::StdRng::from_seed(&[seed]);
+    }
+
+    pub fn get_seed(&self) -> usize {
+        self.seed
+    }
+
+    /// Changes the point the Persons head to (by default, just beyond the top left exit).
+    pub fn set_azimuth(&mut self, azimuth: Point) {
+        self.azimuth = azimuth;
+    }
+
     // add rectangular obstacles in the terrain. Poisitions are occupied by -1 values
     pub fn add_obstacle(&mut self, lower_left : Point, upper_right : Point ){
         for x in lower_left.x..upper_right.x + 1 {
@@ -70,45 +224,518 @@ impl Terrain {
                 self.set_pt_val(&Point{x,y}, -1);
             }
         }
+        self.layout_version += 1;
+    }
+
+    /// Sets the traversal cost of a cell : the number of turns a Person
+    /// standing on it needs before it can move again (stairs, rough ground...).
+    /// Costs lower than `DEFAULT_COST` are raised to it.
+    pub fn set_pt_cost(&mut self, point: &Point, cost: u8) {
+        self.costs.set(point.x as usize, point.y as usize, cmp::max(cost, DEFAULT_COST));
+        self.layout_version += 1;
+    }
+
+    pub fn get_pt_cost(&self, point: &Point) -> u8 {
+        self.costs.get(point.x as usize, point.y as usize)
+    }
+
+    // add rectangular zones of identical cost (a corridor, a staircase...)
+    pub fn add_cost_zone(&mut self, lower_left : Point, upper_right : Point, cost : u8){
+        for x in lower_left.x..upper_right.x + 1 {
+            for y in lower_left.y..upper_right.y + 1 {
+                self.set_pt_cost(&Point{x,y}, cost);
+            }
+        }
+    }
+
+    /// Applies a textual cost map to the Terrain.
+    /// Each line is a row of the Terrain, the first line being the top row (as in `Display`).
+    /// Digits `1` to `9` set the cost of the cell, any other character keeps the default cost.
+    /// Rows and columns beyond the Terrain bounds are ignored.
+    pub fn apply_cost_map(&mut self, map: &str) {
+        for (row, line) in map.lines().enumerate().take(self.ysize) {
+            let y = (self.ysize - 1 - row) as isize;
+            for (x, c) in line.chars().enumerate().take(self.xsize) {
+                let cost = c.to_digit(10).map(|d| d as u8).unwrap_or(DEFAULT_COST);
+                self.set_pt_cost(&Point{x: x as isize, y}, cost);
+            }
+        }
+    }
+
+    /// Reads a cost map file (see `apply_cost_map` for the format).
+    pub fn load_cost_map(&mut self, path: &Path) -> io::Result<()> {
+        let mut map = String::new();
+        File::open(path)?.read_to_string(&mut map)?;
+        self.apply_cost_map(&map);
+        Ok(())
+    }
+
+
+    /// Number of turns a Person standing on the point needs before it can move again :
+    /// the cell cost, doubled when a hazard slows the Persons down.
+    pub fn get_traversal_cost(&self, point: &Point) -> u8 {
+        let cost = self.get_pt_cost(point);
+        if self.get_hazard(point) >= self.hazard_rules.slow_level {
+            cost.saturating_mul(2)
+        } else {
+            cost
+        }
+    }
+
+    pub fn set_hazard_rules(&mut self, rules: HazardRules) {
+        self.hazard_rules = rules;
+    }
+
+    pub fn get_hazard_rules(&self) -> &HazardRules {
+        &self.hazard_rules
+    }
+
+    /// Starts a hazard (fire, smoke...) on a point, it will spread at each tick.
+    pub fn ignite(&mut self, point: &Point, intensity: u8) {
+        let current = self.get_hazard(point);
+        if current == 0 && intensity > 0 {
+            let offset = self.get_offset(point);
+            self.hazard_cells.push(offset);
+        }
+        self.hazard.set(point.x as usize, point.y as usize, cmp::max(current, intensity));
+    }
+
+    /// Hazard intensity on a point, 0 if there is no hazard.
+    pub fn get_hazard(&self, point: &Point) -> u8 {
+        self.hazard.get(point.x as usize, point.y as usize)
+    }
+
+    /// Highest hazard intensity in the neighbourhood of a point (the point included) :
+    /// lets Persons feel the hazard gradient before walking into it.
+    pub fn get_hazard_around(&self, center: &Point) -> u8 {
+        let mut max: u8 = 0;
+        for x in cmp::max(center.x - 1, 0)..cmp::min(center.x + 2, self.xsize as isize) {
+            for y in cmp::max(center.y - 1, 0)..cmp::min(center.y + 2, self.ysize as isize) {
+                max = cmp::max(max, self.get_hazard(&Point{x, y}));
+            }
+        }
+        max
+    }
+
+    pub fn is_lethal(&self, point: &Point) -> bool {
+        self.get_hazard(point) >= self.hazard_rules.lethal_level
+    }
+
+    fn is_hazard_blocked(&self, point: &Point) -> bool {
+        self.get_hazard(point) >= self.hazard_rules.block_level
+    }
+
+    // grow existing hazard cells and spread them to their neighbours (obstacles stop the spread)
+    fn spread_hazard(&mut self) {
+        let growth = self.hazard_rules.growth;
+        let burning = self.hazard_cells.len();
+        for idx in 0..burning {
+            let center = self.get_point(self.hazard_cells[idx]);
+            let intensity = self.get_hazard(&center).saturating_add(growth);
+            self.hazard.set(center.x as usize, center.y as usize, intensity);
+
+            for x in cmp::max(center.x - 1, 0)..cmp::min(center.x + 2, self.xsize as isize) {
+                for y in cmp::max(center.y - 1, 0)..cmp::min(center.y + 2, self.ysize as isize) {
+                    let pt = Point{x, y};
+                    if self.get_hazard(&pt) == 0 && self.get_pt_val(&pt) != -1
+                        && self.hazard_rng.next_f32() < self.hazard_rules.spread_probability {
+                        self.ignite(&pt, growth);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Plans a layout change (door, barrier...) for a given tick.
+    /// Events planned for a past tick are applied at the next tick.
+    pub fn schedule_event(&mut self, tick: u64, event: LayoutEvent) {
+        let idx = self.timeline.iter().position(|e| e.tick > tick).unwrap_or(self.timeline.len());
+        self.timeline.insert(idx, ScheduledEvent{tick, event});
+    }
+
+    /// Reads a timeline file and schedules its events (see `events::parse_timeline` for the format).
+    pub fn load_timeline(&mut self, path: &Path) -> io::Result<()> {
+        let mut timeline = String::new();
+        File::open(path)?.read_to_string(&mut timeline)?;
+        for scheduled in parse_timeline(&timeline)? {
+            self.schedule_event(scheduled.tick, scheduled.event);
+        }
+        Ok(())
+    }
+
+    pub fn get_tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Shares a clock with other Terrains (the floors of a Building) : it holds the most advanced
+    /// of their ticks, so that times taken on different floors can be compared.
+    pub fn share_clock(&mut self, clock: Arc<AtomicUsize>) {
+        clock.fetch_max(self.tick as usize, Ordering::Relaxed);
+        self.clock = clock;
+    }
+
+    /// The tick of the clock shared with the other floors, the tick of the Terrain if it is alone.
+    pub fn get_clock(&self) -> u64 {
+        self.clock.load(Ordering::Relaxed) as u64
+    }
+
+    // an idle floor does not tick while the others do : it plays the ticks it missed before a Person arrives
+    fn catch_up_clock(&mut self) {
+        while self.tick < self.get_clock() {
+            self.advance_tick();
+        }
+    }
+
+    /// Changes each time obstacles or costs are modified :
+    /// routing data computed from the layout must be recomputed when it differs.
+    pub fn get_layout_version(&self) -> u64 {
+        self.layout_version
+    }
+
+    /// Number of turns needed to walk from a point to the nearest exit point.
+    /// The distance field is computed the first time it is needed
+    /// and again after each change of the layout.
+    pub fn get_exit_distance(&self, point: &Point) -> f32 {
+        self.get_footprint_exit_distance(point, 1)
+    }
+
+    /// Same as `get_exit_distance` for a Person occupying a square of cells,
+    /// infinite if it cannot get out from there.
+    pub fn get_footprint_exit_distance(&self, anchor: &Point, size: usize) -> f32 {
+        let outdated = match self.exit_fields.borrow().get(&size) {
+            Some(field) => field.get_layout_version() != self.layout_version,
+            None => true,
+        };
+        if outdated {
+            debug!("tick {} : computing the exit distance field of footprint {}", self.tick, size);
+            // the anchors from which the footprint covers an exit point
+            let mut targets: Vec<Point> = Vec::with_capacity(self.exit_points.len() * size * size);
+            for exit in &self.exit_points {
+                let corner = Point{x: exit.x - size as isize + 1, y: exit.y - size as isize + 1};
+                for anchor in corner.footprint(size) {
+                    if !targets.contains(&anchor) && self.is_walkable_footprint(&anchor, size) {
+                        targets.push(anchor);
+                    }
+                }
+            }
+            let field = DistanceField::compute_for_footprint(self, &targets, size);
+            self.exit_fields.borrow_mut().insert(size, field);
+        }
+        self.exit_fields.borrow().get(&size).map_or(f32::INFINITY, |field| field.get(anchor))
+    }
+
+    /// Called by each Person at the end of its turn. Once as many turns as there are
+    /// Persons on the Terrain have been played, the Terrain moves to the next tick.
+    pub fn end_turn(&mut self) {
+        self.turns_in_tick += 1;
+        if self.turns_in_tick >= self.get_active_cnt() {
+            self.advance_tick();
+        }
+    }
+
+    /// Moves the Terrain to the next tick : spreads the hazards and applies the layout events due.
+    pub fn advance_tick(&mut self) {
+        let state = (self.get_active_cnt(), self.exited_cnt);
+        self.history.push(state);
+        self.turns_in_tick = 0;
+        self.tick += 1;
+        self.clock.fetch_max(self.tick as usize, Ordering::Relaxed);
+
+        if !self.hazard_cells.is_empty() {
+            self.spread_hazard();
+        }
+
+        let mut postponed: Vec<LayoutEvent> = Vec::new();
+        while self.timeline.front().is_some_and(|e| e.tick <= self.tick) {
+            let scheduled = self.timeline.pop_front().unwrap();
+            if !self.apply_event(&scheduled.event) {
+                // some cells are occupied by Persons : try again at next tick
+                postponed.push(scheduled.event);
+            }
+        }
+        for event in postponed {
+            let next_tick = self.tick + 1;
+            self.schedule_event(next_tick, event);
+        }
+    }
+
+    // apply a layout event, returns false if it could not be applied on every cell
+    fn apply_event(&mut self, event: &LayoutEvent) -> bool {
+        debug!("tick {} : applying {}", self.tick, event);
+        let (lower_left, upper_right, from, to) = match *event {
+            LayoutEvent::Block(ll, ur) => (ll, ur, 0, -1),
+            LayoutEvent::Clear(ll, ur) => (ll, ur, -1, 0),
+        };
+
+        let mut complete = true;
+        for x in cmp::max(lower_left.x, 0)..cmp::min(upper_right.x + 1, self.xsize as isize) {
+            for y in cmp::max(lower_left.y, 0)..cmp::min(upper_right.y + 1, self.ysize as isize) {
+                let pt = Point{x, y};
+                let val = self.get_pt_val(&pt);
+                if val == from {
+                    self.set_pt_val(&pt, to);
+                } else if val != to {
+                    complete = false; // a Person stands here, it is never crushed by an obstacle
+                }
+            }
+        }
+        self.layout_version += 1;
+        complete
     }
 
 
     /// Returns a random point on the Terrain that is available.
     pub fn get_random_free_point(&self) -> Option<Point> {
         //let mut rng = rand::thread_rng();
-        let seed: &[_] = &[1,]; // declare random generator with constant seed to get consistant results between executions.
+        let seed: &[_] = &[self.seed,]; // declare random generator with the seed of the Terrain to get consistant results between executions.
         let mut rng = rand::StdRng::from_seed(seed);
 
-
-        let mut avl_points : Vec<Point> = Vec::new();
-
-        for idx in 0..self.data.len() {
-            if self.data[idx] == 0 {
-                avl_points.push( self.get_point(idx));
-            };
+        let occupied: usize = self.row_occupation.iter().sum();
+        let sz = self.xsize * self.ysize - occupied;
+        if sz == 0 { // no free position remaining
+            return None;
         }
 
-        let sz = avl_points.len();
-        if sz > 0 { // free positions remaining
-            Some(avl_points.remove(rng.gen_range(0,sz)))
-        } else { // no free position remaining
-            None
+        // free positions remaining : pick one of them, in the order of the rows,
+        // using the rows occupation to avoid listing every free point of the Terrain
+        let mut rank = rng.gen_range(0, sz);
+        for y in 0..self.ysize {
+            let free_in_row = self.xsize - self.row_occupation[y];
+            if rank >= free_in_row {
+                rank -= free_in_row;
+                continue;
+            }
+            for x in 0..self.xsize {
+                if self.data.get(x, y) == 0 {
+                    if rank == 0 {
+                        return Some(Point{x: x as isize, y: y as isize});
+                    }
+                    rank -= 1;
+                }
+            }
         }
+        None
     }
 
     pub fn get_exited_cnt(&self) -> usize {
         self.exited_cnt
     }
 
-    /// non thread safe count of persons in the terrain.
+    pub fn get_casualties_cnt(&self) -> usize {
+        self.casualties_cnt
+    }
+
+    /// Total number of turns spent by Persons on hazard cells.
+    pub fn get_exposure_ticks(&self) -> u64 {
+        self.exposure_ticks
+    }
+
+    /// Persons that left the Terrain by a stair (see `set_stair_exits`).
+    pub fn get_stair_cnt(&self) -> usize {
+        self.stair_cnt
+    }
+
+    /// Number of Persons that left the Terrain, by an exit, by a stair or as casualties.
+    pub fn get_left_cnt(&self) -> usize {
+        self.exited_cnt + self.stair_cnt + self.casualties_cnt
+    }
+
+    /// Number of Persons placed on the Terrain that have not left it yet.
+    pub fn get_active_cnt(&self) -> usize {
+        self.placed_cnt.saturating_sub(self.get_left_cnt())
+    }
+
+    /// A Person engulfed by a hazard is removed from the Terrain.
+    pub fn remove_casualty(&mut self, point: &Point) {
+        self.set_pt_val(point, 0);
+        self.casualties_cnt += 1;
+        debug!("terrain casualties :{}", self.casualties_cnt);
+    }
+
+    /// A Person spent a turn on a hazard cell.
+    pub fn record_exposure(&mut self) {
+        self.exposure_ticks += 1;
+    }
+
+    /// Occupies a free point with a Person
+    pub fn place_person(&mut self, point: &Point, id: usize) {
+        if self.get_active_cnt() == 0 {
+            self.catch_up_clock();
+        }
+        self.set_pt_val(point, id as isize);
+        self.placed_cnt += 1;
+    }
+
+    /// Number of cells occupied by Persons in the square of the given radius around a point.
+    pub fn count_persons_around(&self, center: &Point, radius: isize) -> usize {
+        let mut count = 0;
+        for x in cmp::max(center.x - radius, 0)..cmp::min(center.x + radius + 1, self.xsize as isize) {
+            for y in cmp::max(center.y - radius, 0)..cmp::min(center.y + radius + 1, self.ysize as isize) {
+                if self.get_pt_val(&Point{x, y}) > 0 {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Places a Person occupying the square of the given side above and right of the point.
+    pub fn place_footprint(&mut self, anchor: &Point, size: usize, id: usize) {
+        self.place_person(anchor, id);
+        for cell in anchor.footprint(size).iter().skip(1) {
+            self.set_pt_val(cell, id as isize);
+        }
+    }
+
+    /// Frees every cell of a footprint.
+    pub fn remove_footprint(&mut self, anchor: &Point, size: usize) {
+        for cell in anchor.footprint(size) {
+            self.set_pt_val(&cell, 0);
+        }
+    }
+
+    /// true if at least one cell of the footprint is an exit point.
+    pub fn is_exit_footprint(&self, anchor: &Point, size: usize) -> bool {
+        if size == 1 {
+            return self.exit_points.contains(anchor);
+        }
+        anchor.footprint(size).iter().any(|cell| self.exit_points.contains(cell))
+    }
+
+    /// A free point out of the exits where a Person of the given footprint can be placed :
+    /// the first one fitting after a random free point (see `get_random_free_point`).
+    /// Large footprints are only placed where they can reach an exit (a gap may be too narrow).
+    pub fn get_random_free_area(&self, size: usize) -> Option<Point> {
+        let start = self.get_random_free_point()?;
+        let total = self.xsize * self.ysize;
+        let first = self.get_offset(&start);
+        (0..total).map(|k| self.get_point((first + k) % total))
+            .find(|pt| self.is_free_area(pt, size))
+    }
+
+    // a Person of the given footprint can be placed there, and get out from there if it is large
+    fn is_free_area(&self, anchor: &Point, size: usize) -> bool {
+        self.check_valid_footprint(anchor, size, 0) &&
+            !self.is_exit_footprint(anchor, size) &&
+            (size == 1 || self.get_footprint_exit_distance(anchor, size).is_finite())
+    }
+
+    /// The free points of a rectangular zone where a Person of the given footprint can be placed.
+    pub fn get_free_areas_in(&self, lower_left: &Point, upper_right: &Point, size: usize) -> Vec<Point> {
+        let mut result = Vec::new();
+        for x in cmp::max(lower_left.x, 0)..cmp::min(upper_right.x + 1, self.xsize as isize) {
+            for y in cmp::max(lower_left.y, 0)..cmp::min(upper_right.y + 1, self.ysize as isize) {
+                let anchor = Point{x, y};
+                if self.is_free_area(&anchor, size) {
+                    result.push(anchor);
+                }
+            }
+        }
+        result
+    }
+
+    /// A free point as close as possible to a given one, where a Person of the given footprint
+    /// can be placed (to place the members of a group next to each other).
+    pub fn get_free_area_near(&self, center: &Point, size: usize) -> Option<Point> {
+        for radius in 1..cmp::max(self.xsize, self.ysize) as isize {
+            for x in (center.x - radius)..(center.x + radius + 1) {
+                for y in (center.y - radius)..(center.y + radius + 1) {
+                    let pt = Point{x, y};
+                    let on_ring = (x - center.x).abs() == radius || (y - center.y).abs() == radius;
+                    if on_ring && self.is_free_area(&pt, size) {
+                        return Some(pt);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Registers a member of a group standing on the Terrain.
+    pub fn join_group(&mut self, group: usize, id: usize, position: &Point) {
+        let members = self.groups.entry(group).or_default();
+        members.retain(|&(member, _)| member != id);
+        members.push((id, *position));
+    }
+
+    pub fn move_in_group(&mut self, group: usize, id: usize, position: &Point) {
+        if let Some(member) = self.groups.get_mut(&group).and_then(|m| m.iter_mut().find(|m| m.0 == id)) {
+            member.1 = *position;
+        }
+    }
+
+    /// The member left the Terrain (escaped, casualty or down a stair).
+    pub fn leave_group(&mut self, group: usize, id: usize) {
+        if let Some(members) = self.groups.get_mut(&group) {
+            members.retain(|&(member, _)| member != id);
+        }
+    }
+
+    /// Ids and positions of the members of a group standing on the Terrain.
+    pub fn get_group_members(&self, group: usize) -> &[(usize, Point)] {
+        self.groups.get(&group).map_or(&[], |members| members.as_slice())
+    }
+
+    /// Records that the Person `id`, blocked at `from`, wants the cell `to` occupied by another Person.
+    /// If that Person already wants `from`, both exchange their cells at once and true is returned :
+    /// the other Person gets its new cell from `take_swap`.
+    pub fn swap_or_wait(&mut self, id: usize, from: &Point, to: &Point) -> bool {
+        let other = self.get_pt_val(to);
+        let mutual = other > 0 && self.intents.get(&(other as usize))
+            .is_some_and(|&(other_from, other_to)| other_from == *to && other_to == *from);
+        if !mutual {
+            self.intents.insert(id, (*from, *to));
+            return false;
+        }
+        self.intents.remove(&id);
+        self.intents.remove(&(other as usize));
+        self.set_pt_val(from, other);
+        self.set_pt_val(to, id as isize);
+        self.last_moves.insert(id as isize, Point{x: to.x - from.x, y: to.y - from.y});
+        self.last_moves.insert(other, Point{x: from.x - to.x, y: from.y - to.y});
+        self.swapped.insert(other as usize, *from);
+        self.swaps_cnt += 1;
+        debug!("tick {} : {} and {} swap {} and {}", self.tick, id, other, from, to);
+        true
+    }
+
+    /// New cell of a Person moved by a swap since its last turn.
+    pub fn take_swap(&mut self, id: usize) -> Option<Point> {
+        self.swapped.remove(&id)
+    }
+
+    /// The Person does not want to swap anymore (it moved or left the Terrain).
+    pub fn forget_intent(&mut self, id: usize) {
+        self.intents.remove(&id);
+    }
+
+    pub fn get_swaps_cnt(&self) -> usize {
+        self.swaps_cnt
+    }
+
+    /// Number of cells occupied by Persons (a Person may occupy several cells).
     pub fn count_persons_in_terrain(&self) -> usize {
         let mut count: usize = 0;
-        for val in self.data.as_slice() {
-            if (*val != 0) &&  *val != -1 { count = count + 1; }
-        }
+        self.data.for_each_set(|_, _, val| {
+            if val != -1 { count += 1; }
+        });
         count
     }
 
+    /// Index of the storage chunk holding a point : points of a same chunk are stored together,
+    /// chunks are natural units to split the Terrain between locks or threads.
+    pub fn get_chunk_id(&self, point: &Point) -> usize {
+        self.data.chunk_index(point.x as usize, point.y as usize)
+    }
+
+    /// Approximate memory used by the cells of the Terrain, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.data.memory_size() + self.costs.memory_size() + self.hazard.memory_size()
+            + self.occupied_ticks.memory_size() + self.occupied_since.memory_size() + self.refused_moves.memory_size()
+            + self.exit_fields.borrow().values().map(|field| field.memory_size()).sum::<usize>()
+            + self.row_occupation.len() * mem::size_of::<usize>()
+    }
+
     fn get_offset(&self, point : &Point) ->usize {
         ( self.xsize * point.y as usize ) + point.x as usize
     }
@@ -120,27 +747,109 @@ impl Terrain {
     }
 
     pub fn set_pt_val(&mut self, point: &Point, value : isize) {
-        let offset = self.get_offset(point);
-        self.data[offset] = value;
+        let (x, y) = (point.x as usize, point.y as usize);
+        let previous = self.data.get(x, y);
+        if previous == 0 && value != 0 {
+            self.row_occupation[y] += 1;
+        } else if previous != 0 && value == 0 {
+            self.row_occupation[y] -= 1;
+        }
+        // the occupation of a cell is counted when its Person leaves (a swap does not free it)
+        if previous <= 0 && value > 0 {
+            self.occupied_since.set(x, y, self.tick as u32);
+        } else if previous > 0 && value <= 0 {
+            let ticks = self.occupied_ticks.get(x, y) + self.tick as u32 - self.occupied_since.get(x, y);
+            self.occupied_ticks.set(x, y, ticks);
+        }
+        self.data.set(x, y, value as i32);
     }
 
     pub fn get_pt_val(&self, point: &Point) -> isize {
-        let offset = self.get_offset(point);
-        self.data[offset]
+        self.data.get(point.x as usize, point.y as usize) as isize
+    }
+
+    /// Ticks the cell was occupied by a Person so far (at the end of each tick).
+    pub fn get_occupied_ticks(&self, point: &Point) -> u32 {
+        let (x, y) = (point.x as usize, point.y as usize);
+        let ticks = self.occupied_ticks.get(x, y);
+        if self.data.get(x, y) > 0 {
+            ticks + self.tick as u32 - self.occupied_since.get(x, y)
+        } else {
+            ticks
+        }
+    }
+
+    /// Moves into the cell refused so far because it was taken (by a Person, an obstacle or a hazard).
+    pub fn get_refused_moves(&self, point: &Point) -> u32 {
+        self.refused_moves.get(point.x as usize, point.y as usize)
+    }
+
+    /// Calls `f(point, occupied ticks, refused moves)` for every cell with a count, row by row from y = 0 :
+    /// only the cells set in the occupancy and refusal grids (and the occupied ones) are visited.
+    pub fn for_each_heat<F: FnMut(&Point, u32, u32)>(&self, mut f: F) {
+        let mut cells: BTreeMap<(usize, usize), u32> = BTreeMap::new(); // refused moves of each cell, by row
+        self.occupied_ticks.for_each_set(|x, y, _| { cells.entry((y, x)).or_insert(0); });
+        self.data.for_each_set(|x, y, val| if val > 0 { cells.entry((y, x)).or_insert(0); });
+        self.refused_moves.for_each_set(|x, y, val| { cells.insert((y, x), val); });
+        for (&(y, x), &refused) in &cells {
+            let point = Point{x: x as isize, y: y as isize};
+            f(&point, self.get_occupied_ticks(&point), refused);
+        }
+    }
+
+    /// A Person could not move into the cell : it was taken.
+    pub fn record_refused_move(&mut self, point: &Point) {
+        if self.is_in_bounds(point) {
+            let (x, y) = (point.x as usize, point.y as usize);
+            let refused = self.refused_moves.get(x, y) + 1;
+            self.refused_moves.set(x, y, refused);
+        }
+    }
+
+    // the Person `id` left by the exit under its footprint
+    fn record_exit(&mut self, id: isize, anchor: &Point, size: usize) {
+        if self.stair_exits {
+            self.stair_cnt += 1;
+        } else {
+            self.exited_cnt += 1;
+            debug!("terrain exits :{}", self.exited_cnt );
+        }
+        self.last_moves.remove(&id);
+        let cells = anchor.footprint(size);
+        if let Some(exit) = self.exits.iter().position(|e| e.cells.iter().any(|c| cells.contains(c))) {
+            self.exit_usage[exit] += 1;
+        }
+    }
+
+    /// Last move of a Person, from its previous cell to its current one.
+    pub fn get_last_move(&self, id: isize) -> Option<Point> {
+        self.last_moves.get(&id).cloned()
+    }
+
+    /// Persons on the Terrain and Persons escaped so far, at the end of each past tick.
+    pub fn get_history(&self) -> &[(usize, usize)] {
+        &self.history
+    }
+
+    /// Number of Persons that left by each exit (see `get_exits`).
+    pub fn get_exit_usage(&self) -> &[usize] {
+        &self.exit_usage
     }
 
     /// take the value at src, and write it at dst, reset src to 0 ("free")
     /// we shall make this function thread safe : no 2 moves at the same time
     pub fn move_src_to_dst(&mut self, src : &Point, dst : &Point) -> Option<()> {
 
-        if self.get_pt_val(dst) != 0 { // Trying to move to an occupied position
+        if self.get_pt_val(dst) != 0 || self.is_hazard_blocked(dst) { // Trying to move to an occupied position
+             self.record_refused_move(dst);
              return None // no move and early exit
         } else if self.exit_points.contains(dst) { // do not change the value of exit points
-            self.exited_cnt = self.exited_cnt + 1;
-            debug!("terrain exits :{}", self.exited_cnt );
+            let val = self.get_pt_val(src);
+            self.record_exit(val, dst, 1);
         } else {
             let val = self.get_pt_val(src);
             self.set_pt_val(dst,val);
+            self.last_moves.insert(val, Point{x: dst.x - src.x, y: dst.y - src.y});
             //self.data[dst.x as usize][dst.y as usize] = self.data[src.x as usize][src.y as usize];
         }
         self.set_pt_val(src,0);
@@ -171,25 +880,92 @@ impl Terrain {
 
     // check if some position is candidate to a move (not occupied nor an obstacle)
     fn check_valid(&self, x_prob: isize, y_prob: isize) -> bool {
-        (x_prob >= 0 && x_prob < XSIZE as isize) && // check x_prob within Terrain bounds
-            (y_prob >= 0 && y_prob < YSIZE as isize) && // check y_prob within Terrain bounds
-            self.get_pt_val(&Point{x: x_prob, y: y_prob}) ==0
+        (x_prob >= 0 && x_prob < self.xsize as isize) && // check x_prob within Terrain bounds
+            (y_prob >= 0 && y_prob < self.ysize as isize) && // check y_prob within Terrain bounds
+            self.get_pt_val(&Point{x: x_prob, y: y_prob}) ==0 &&
+            !self.is_hazard_blocked(&Point{x: x_prob, y: y_prob})
                // .get.data[x_prob as usize][y_prob as usize] == 0 // check (x_pos, y_pos) is free
     }
 
 
-    fn check_valid_pt(&self, prob_point : &Point) -> bool {
+    /// Same as `list_possible_moves` for a Person occupying a square of cells.
+    pub fn list_possible_footprint_moves(&self, anchor: &Point, size: usize) -> Vec<Point> {
+        if size == 1 {
+            return self.list_possible_moves(anchor);
+        }
+        let id = self.get_pt_val(anchor);
+        let mut result: Vec<Point> = Vec::with_capacity(8);
+        for x in (anchor.x - 1)..(anchor.x + 2) {
+            for y in (anchor.y - 1)..(anchor.y + 2) {
+                let candidate = Point{x, y};
+                if candidate != *anchor && self.check_valid_footprint(&candidate, size, id) {
+                    result.push(candidate);
+                }
+            }
+        }
+        result
+    }
+
+    /// Same as `move_src_to_dst` for a Person occupying a square of cells :
+    /// the cells of the destination must be free or already occupied by the Person.
+    pub fn move_footprint(&mut self, src : &Point, dst : &Point, size: usize) -> Option<()> {
+        if size == 1 {
+            return self.move_src_to_dst(src, dst);
+        }
+        let id = self.get_pt_val(src);
+        if !self.check_valid_footprint(dst, size, id) {
+            for cell in dst.footprint(size) {
+                if !self.check_valid_footprint(&cell, 1, id) {
+                    self.record_refused_move(&cell);
+                }
+            }
+            return None;
+        }
+        self.remove_footprint(src, size);
+        if self.is_exit_footprint(dst, size) {
+            self.record_exit(id, dst, size);
+        } else {
+            for cell in dst.footprint(size) {
+                self.set_pt_val(&cell, id);
+            }
+            self.last_moves.insert(id, Point{x: dst.x - src.x, y: dst.y - src.y});
+        }
+        Some(())
+    }
+
+    /// true if no cell of the footprint is out of the Terrain or an obstacle (Persons are ignored).
+    pub fn is_walkable_footprint(&self, anchor: &Point, size: usize) -> bool {
+        anchor.footprint(size).iter().all(|cell| self.is_in_bounds(cell) && self.get_pt_val(cell) != -1)
+    }
+
+    /// true if every cell of the footprint is within the Terrain, not blocked by a hazard,
+    /// and free or occupied by the Person `id`.
+    pub fn check_valid_footprint(&self, anchor: &Point, size: usize, id: isize) -> bool {
+        anchor.footprint(size).iter().all(|cell| {
+            self.is_in_bounds(cell) && !self.is_hazard_blocked(cell) && {
+                let val = self.get_pt_val(cell);
+                val == 0 || (val == id && id > 0)
+            }
+        })
+    }
+
+    pub fn is_in_bounds(&self, point : &Point) -> bool {
+        (point.x >= 0 && point.x < self.xsize as isize) && (point.y >= 0 && point.y < self.ysize as isize)
+    }
+
+    pub fn check_valid_pt(&self, prob_point : &Point) -> bool {
         (prob_point.x >= 0 && prob_point.x < self.xsize as isize) && // check x_prob within Terrain bounds
             (prob_point.y >= 0 && prob_point.y < self.ysize as isize) && // check y_prob within Terrain bounds
-            self.get_pt_val(prob_point) == 0 // check (x_pos, y_pos) is free
+            self.get_pt_val(prob_point) == 0 && // check (x_pos, y_pos) is free
+            !self.is_hazard_blocked(prob_point)
     }
 }
 
 impl fmt::Display for Terrain {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Terrain {{\n")?;
-        for y in (0..YSIZE).rev() {
-            for x in 0..XSIZE {
+        for y in (0..self.ysize).rev() {
+            for x in 0..self.xsize {
                 write!(f, "({},{})={} \t", x, y, self.get_pt_val(&Point{x : x as isize, y : y as isize}))?;
             }
             write!(f, "\n")?;
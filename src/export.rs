@@ -0,0 +1,452 @@
+use domain::report::{EvacuationReport, ExitReport};
+use statistics::{PerfResult, PerfSummary};
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+// ******
+// FORMAT
+// ******
+/// File formats of the measures export : one line per row in both of them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Csv,
+    Jsonl,
+}
+
+impl Format {
+    /// The format matching the extension of a file (`.csv`, `.jsonl` or `.json`).
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension().and_then(|e| e.to_str()).and_then(|e| e.parse::<Format>().ok())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "jsonl" | "json" => Ok(Format::Jsonl),
+            _ => Err(format!("\"{}\" is not an export format, expected csv or jsonl", s)),
+        }
+    }
+}
+
+// ***
+// ROW
+// ***
+/// The configuration a row was measured with.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RunSetup {
+    pub scenario: usize,
+    pub persons: usize,
+    pub xsize: usize,
+    pub ysize: usize,
+    pub seed: usize,
+    /// the other options of the run and the repetitions, as hashed by `config_hash`
+    pub config: u64,
+}
+
+/// A hash of the description of a configuration (FNV-1a, kept within the integers a json reader handles exactly) :
+/// the rows measured with different configurations are told apart.
+pub fn config_hash(description: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in description.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash & ((1 << 53) - 1)
+}
+
+const COLUMNS: &[&str] = &["kind", "scenario", "persons", "xsize", "ysize", "seed", "config", "run", "runs",
+    "time", "utime", "stime", "maxrss", "nvcsw", "nivcsw", "minflt", "majflt", "threads", "clock_t",
+    "lock_acquisitions", "lock_contended", "lock_wait", "lock_max_wait", "lock_hold", "lock_threads",
+    "cpu_threads", "thread_cpu_min", "thread_cpu_median", "thread_cpu_max", "thread_cpu_gini",
+    "time_stddev", "time_min", "time_max", "utime_stddev", "stime_stddev",
+    "ticks", "escaped", "casualties", "exposure_ticks", "swaps", "exit_imbalance", "mean_occupancy", "throughput",
+    "evacuation_ticks", "escape_time_mean", "escape_time_median", "escape_time_p95", "distance", "escape_curve"];
+
+// a value of a row : missing values are empty in csv and null in json,
+// lists are separated by semicolons in csv and are arrays in json
+enum Value {
+    Text(&'static str),
+    Int(u64),
+    Real(f64),
+    List(Vec<f64>),
+    Missing,
+}
+
+impl Value {
+    fn csv(&self) -> String {
+        match *self {
+            Value::Text(t) => t.to_string(),
+            Value::Int(i) => i.to_string(),
+            Value::Real(r) => r.to_string(),
+            Value::List(ref l) => l.iter().map(|r| r.to_string()).collect::<Vec<String>>().join(";"),
+            Value::Missing => String::new(),
+        }
+    }
+
+    fn json(&self) -> String {
+        match *self {
+            Value::Text(t) => format!("\"{}\"", t),
+            Value::Int(i) => i.to_string(),
+            Value::Real(r) if r.is_finite() => r.to_string(),
+            Value::List(ref l) => format!("[{}]", l.iter().map(|r| r.to_string()).collect::<Vec<String>>().join(",")),
+            _ => "null".to_string(),
+        }
+    }
+}
+
+fn secs(d: Duration) -> Value {
+    Value::Real(d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9)
+}
+
+// the values of a row, named by their column
+type Row = Vec<(&'static str, Value)>;
+
+// the same columns without their values
+fn missing(row: Row) -> Row {
+    row.into_iter().map(|(column, _)| (column, Value::Missing)).collect()
+}
+
+fn perf_values(perf: Option<&PerfResult>) -> Row {
+    let p = match perf {
+        Some(p) => p,
+        None => return missing(perf_values(Some(&PerfResult::default()))),
+    };
+    vec![("time", secs(p.time)), ("utime", secs(p.utime)), ("stime", secs(p.stime)), ("maxrss", Value::Real(p.maxrss)),
+         ("nvcsw", Value::Int(p.nvcsw)), ("nivcsw", Value::Int(p.nivcsw)), ("minflt", Value::Int(p.minflt)),
+         ("majflt", Value::Int(p.majflt)), ("threads", Value::Int(p.threads as u64)), ("clock_t", Value::Int(p.clock_t)),
+         ("lock_acquisitions", Value::Int(p.lock.acquisitions)), ("lock_contended", Value::Int(p.lock.contended)),
+         ("lock_wait", secs(p.lock.wait)), ("lock_max_wait", secs(p.lock.max_wait)), ("lock_hold", secs(p.lock.hold)),
+         ("lock_threads", Value::Int(p.lock.contending_threads as u64)),
+         ("cpu_threads", Value::Int(p.thread_cpu.threads as u64)), ("thread_cpu_min", secs(p.thread_cpu.min)),
+         ("thread_cpu_median", secs(p.thread_cpu.median)), ("thread_cpu_max", secs(p.thread_cpu.max)),
+         ("thread_cpu_gini", Value::Real(p.thread_cpu.gini))]
+}
+
+// spread of the runs of an aggregate row
+fn spread_values(summary: Option<&PerfSummary>) -> Row {
+    let value = |f: &dyn Fn(&PerfSummary) -> Duration| summary.map_or(Value::Missing, |s| secs(f(s)));
+    vec![("time_stddev", value(&|s| s.time.stddev)), ("time_min", value(&|s| s.time.min)), ("time_max", value(&|s| s.time.max)),
+         ("utime_stddev", value(&|s| s.utime.stddev)), ("stime_stddev", value(&|s| s.stime.stddev))]
+}
+
+// domain metrics averaged over the reports
+fn domain_values(reports: &[EvacuationReport]) -> Row {
+    if reports.is_empty() {
+        return missing(domain_values(&[EvacuationReport::default()]));
+    }
+    let cnt = reports.len() as f64;
+    let mean = |f: &dyn Fn(&EvacuationReport) -> f64| Value::Real(reports.iter().map(f).sum::<f64>() / cnt);
+    let steady = reports.iter().all(|r| r.steady.is_some());
+    vec![
+        ("ticks", mean(&|r| r.ticks as f64)),
+        ("escaped", mean(&|r| r.escaped as f64)),
+        ("casualties", mean(&|r| r.casualties as f64)),
+        ("exposure_ticks", mean(&|r| r.exposure_ticks as f64)),
+        ("swaps", mean(&|r| r.swaps as f64)),
+        ("exit_imbalance", mean(&|r| ExitReport::from_usage(&r.exit_usage).imbalance as f64)),
+        ("mean_occupancy", if steady { mean(&|r| r.steady.as_ref().unwrap().mean_occupancy as f64) } else { Value::Missing }),
+        ("throughput", if steady { mean(&|r| r.steady.as_ref().unwrap().throughput as f64) } else { Value::Missing }),
+        ("evacuation_ticks", mean(&|r| r.outcome.evacuation_ticks as f64)),
+        ("escape_time_mean", mean(&|r| r.outcome.mean_time_to_escape as f64)),
+        ("escape_time_median", mean(&|r| r.outcome.median_time_to_escape as f64)),
+        ("escape_time_p95", mean(&|r| r.outcome.p95_time_to_escape as f64)),
+        ("distance", mean(&|r| r.outcome.distance as f64)),
+        ("escape_curve", mean_curve(reports)),
+    ]
+}
+
+// Persons escaped at each tick averaged over the reports, a run that ended earlier escapes no one after its end
+fn mean_curve(reports: &[EvacuationReport]) -> Value {
+    let len = reports.iter().map(|r| r.outcome.escape_curve.len()).max().unwrap_or(0);
+    let mut curve = vec![0.; len];
+    for report in reports {
+        for (tick, &cnt) in report.outcome.escape_curve.iter().enumerate() {
+            curve[tick] += cnt as f64 / reports.len() as f64;
+        }
+    }
+    Value::List(curve)
+}
+
+// ********
+// EXPORTER
+// ********
+/// Writes the measures of the runs, and their aggregation, to a file the notebooks can read.
+pub struct Exporter<W: Write> {
+    format: Format,
+    out: W,
+}
+
+impl Exporter<BufWriter<File>> {
+
+    /// Creates the file (its format is given by its extension) and writes the csv header.
+    pub fn create(path: &Path) -> io::Result<Exporter<BufWriter<File>>> {
+        let format = Format::from_path(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+            format!("unknown export format of {}, expected a .csv or .jsonl file", path.display())))?;
+        let file = File::create(path)?;
+        Exporter::new(format, BufWriter::new(file))
+    }
+
+    /// Goes on with a file written by an interrupted export : the rows of the configurations
+    /// without an aggregate row are dropped, the ones of the configurations completed are kept
+    /// and returned to be left out. A file that does not exist yet is created.
+    /// The rows must have been measured with the same `config` (see `RunSetup`), they would not compare otherwise.
+    pub fn resume(path: &Path, config: u64) -> io::Result<(Exporter<BufWriter<File>>, Vec<RunSetup>)> {
+        if !path.exists() {
+            return Ok((Exporter::create(path)?, Vec::new()));
+        }
+        let (format, header, lines) = read_lines(path)?;
+        if header != COLUMNS {
+            // the rows of the bench are appended : they must have the columns of the file
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("{} does not hold the columns of this version, start another file", path.display())));
+        }
+        let rows: Vec<(String, Option<(bool, RunSetup)>)> = lines.into_iter()
+            .map(|l| { let row = parse_row(format, &header, &l); (l, row) }).collect();
+        if rows.iter().any(|r| r.1.is_some_and(|(_, setup)| setup.config != config)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("{} was measured with other options or repetitions, start another file", path.display())));
+        }
+        let done: Vec<RunSetup> = rows.iter().filter_map(|r| match r.1 {
+            Some((true, setup)) => Some(setup),
+            _ => None,
+        }).collect();
+        // the rows kept are written aside then moved over the file : an interrupted rewrite loses nothing
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".resume");
+        let tmp = path.with_file_name(name);
+        let mut out = Exporter::new(format, BufWriter::new(File::create(&tmp)?))?.out;
+        for (line, row) in rows {
+            if row.is_some_and(|(_, setup)| done.contains(&setup)) {
+                writeln!(out, "{}", line)?;
+            }
+        }
+        out.flush()?;
+        out.get_ref().sync_all()?;
+        drop(out);
+        fs::rename(&tmp, path)?;
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        let exporter = Exporter { format, out: BufWriter::new(file) };
+        Ok((exporter, done))
+    }
+}
+
+/// The aggregate row of a configuration, as read back from an export (durations in seconds).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AggregateRow {
+    pub setup: RunSetup,
+    pub time: f64,
+    pub time_stddev: f64,
+    pub utime: f64,
+    pub utime_stddev: f64,
+    pub stime: f64,
+    pub stime_stddev: f64,
+    pub maxrss: f64,
+}
+
+/// Reads the aggregate rows of an export, in the order of the file
+/// (the values missing from the files of older versions are 0).
+pub fn read_aggregates(path: &Path) -> io::Result<Vec<AggregateRow>> {
+    let (format, header, lines) = read_lines(path)?;
+    let mut aggregates = Vec::new();
+    for line in &lines {
+        if let Some((true, setup)) = parse_row(format, &header, line) {
+            let fields = parse_fields(format, &header, line);
+            let value = |column: &str| {
+                let idx = COLUMNS.iter().position(|c| *c == column).unwrap();
+                fields[idx].parse::<f64>().unwrap_or(0.)
+            };
+            aggregates.push(AggregateRow { setup, time: value("time"), time_stddev: value("time_stddev"),
+                utime: value("utime"), utime_stddev: value("utime_stddev"), stime: value("stime"), stime_stddev: value("stime_stddev"),
+                maxrss: value("maxrss") });
+        }
+    }
+    Ok(aggregates)
+}
+
+// the format, the columns and the rows of an export
+fn read_lines(path: &Path) -> io::Result<(Format, Vec<String>, Vec<String>)> {
+    let format = Format::from_path(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+        format!("unknown export format of {}, expected a .csv or .jsonl file", path.display())))?;
+    let mut lines: Vec<String> = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        lines.push(line?);
+    }
+    // json rows name their values
+    let header: Vec<String> = match format {
+        Format::Csv if !lines.is_empty() => lines.remove(0).split(',').map(|c| c.to_string()).collect(),
+        _ => COLUMNS.iter().map(|c| c.to_string()).collect(),
+    };
+    if !COLUMNS[..6].iter().all(|c| header.iter().any(|h| h == c)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+            format!("{} does not hold the columns of an export", path.display())));
+    }
+    Ok((format, header, lines))
+}
+
+// the values of a row in the order of the columns of this version, empty when missing
+fn parse_fields(format: Format, header: &[String], line: &str) -> Vec<String> {
+    match format {
+        Format::Csv => {
+            let values: Vec<&str> = line.split(',').collect();
+            COLUMNS.iter().map(|c| header.iter().position(|h| h == c).and_then(|idx| values.get(idx))
+                .map_or(String::new(), |v| v.to_string())).collect()
+        }
+        Format::Jsonl => {
+            let fields = json_fields(line).unwrap_or_default();
+            COLUMNS.iter().map(|c| fields.iter().find(|f| f.0 == *c).and_then(|f| f.1.clone()).unwrap_or_default()).collect()
+        }
+    }
+}
+
+// whether a row is an aggregate one, and the configuration it was measured with
+fn parse_row(format: Format, header: &[String], line: &str) -> Option<(bool, RunSetup)> {
+    let fields = parse_fields(format, header, line);
+    let aggregate = match fields[0].as_str() {
+        "aggregate" => true,
+        "run" => false,
+        _ => return None,
+    };
+    let value = |idx: usize| fields[idx].parse::<usize>().ok();
+    Some((aggregate, RunSetup { scenario: value(1)?, persons: value(2)?, xsize: value(3)?, ysize: value(4)?, seed: value(5)?,
+        config: fields[6].parse::<u64>().unwrap_or(0) })) // older files have no config
+}
+
+// the keys and values of a flat json object, None if it is not one : strings are unquoted,
+// arrays are kept whole and null values are None
+fn json_fields(line: &str) -> Option<Vec<(String, Option<String>)>> {
+    let mut chars = line.chars().peekable();
+    skip_spaces(&mut chars);
+    if chars.next()? != '{' {
+        return None;
+    }
+    let mut fields = Vec::new();
+    loop {
+        skip_spaces(&mut chars);
+        match chars.next()? {
+            '"' => {}
+            '}' if fields.is_empty() => return Some(fields),
+            _ => return None,
+        }
+        let key = json_string(&mut chars)?;
+        skip_spaces(&mut chars);
+        if chars.next()? != ':' {
+            return None;
+        }
+        skip_spaces(&mut chars);
+        let value = if chars.peek() == Some(&'"') {
+            chars.next();
+            Some(json_string(&mut chars)?)
+        } else {
+            let mut value = String::new();
+            let mut depth = 0;
+            while let Some(&c) = chars.peek() {
+                if depth == 0 && (c == ',' || c == '}') {
+                    break;
+                }
+                match c { '[' => depth += 1, ']' => depth -= 1, _ => {} }
+                value.push(c);
+                chars.next();
+            }
+            let value = value.trim();
+            if value == "null" { None } else { Some(value.to_string()) }
+        };
+        fields.push((key, value));
+        skip_spaces(&mut chars);
+        match chars.next()? {
+            ',' => {}
+            '}' => return Some(fields),
+            _ => return None,
+        }
+    }
+}
+
+// a json string, after its opening quote
+fn json_string<I: Iterator<Item = char>>(chars: &mut I) -> Option<String> {
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => string.push(match chars.next()? { 'n' => '\n', 't' => '\t', c => c }),
+            c => string.push(c),
+        }
+    }
+}
+
+fn skip_spaces<I: Iterator<Item = char>>(chars: &mut Peekable<I>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+impl<W: Write> Exporter<W> {
+
+    pub fn new(format: Format, out: W) -> io::Result<Exporter<W>> {
+        let mut exporter = Exporter { format, out };
+        if format == Format::Csv {
+            writeln!(exporter.out, "{}", COLUMNS.join(","))?;
+        }
+        Ok(exporter)
+    }
+
+    /// A row for a single run (`perf` is None when the run was not measured).
+    pub fn write_run(&mut self, setup: &RunSetup, run: usize, perf: Option<&PerfResult>, report: &EvacuationReport) -> io::Result<()> {
+        let mut values = vec![("kind", Value::Text("run"))];
+        values.extend(setup_values(setup));
+        values.push(("run", Value::Int(run as u64)));
+        values.push(("runs", Value::Int(1)));
+        values.extend(perf_values(perf));
+        values.extend(spread_values(None));
+        values.extend(domain_values(::std::slice::from_ref(report)));
+        self.write_values(&values)
+    }
+
+    /// The aggregate row of a configuration : the mean of the runs kept and their spread,
+    /// the domain metrics are averaged over all the runs.
+    pub fn write_aggregate(&mut self, setup: &RunSetup, summary: &PerfSummary, reports: &[EvacuationReport]) -> io::Result<()> {
+        let mut values = vec![("kind", Value::Text("aggregate"))];
+        values.extend(setup_values(setup));
+        values.push(("run", Value::Missing));
+        values.push(("runs", Value::Int(summary.runs as u64)));
+        values.extend(perf_values(Some(&summary.mean)));
+        values.extend(spread_values(Some(summary)));
+        values.extend(domain_values(reports));
+        self.write_values(&values)
+    }
+
+    fn write_values(&mut self, values: &[(&'static str, Value)]) -> io::Result<()> {
+        // a value out of place would shift the columns of the row
+        if values.len() != COLUMNS.len() || values.iter().zip(COLUMNS).any(|(&(c, _), column)| c != *column) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("the row {:?} does not match the columns of the export", values.iter().map(|v| v.0).collect::<Vec<_>>())));
+        }
+        let line = match self.format {
+            Format::Csv => values.iter().map(|v| v.1.csv()).collect::<Vec<_>>().join(","),
+            Format::Jsonl => format!("{{{}}}", values.iter()
+                .map(|&(c, ref v)| format!("\"{}\":{}", c, v.json())).collect::<Vec<_>>().join(",")),
+        };
+        writeln!(self.out, "{}", line)?;
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+fn setup_values(setup: &RunSetup) -> Row {
+    vec![("scenario", Value::Int(setup.scenario as u64)), ("persons", Value::Int(setup.persons as u64)),
+         ("xsize", Value::Int(setup.xsize as u64)), ("ysize", Value::Int(setup.ysize as u64)),
+         ("seed", Value::Int(setup.seed as u64)), ("config", Value::Int(setup.config))]
+}
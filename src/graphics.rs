@@ -10,11 +10,13 @@ use self::sdl2::video::{Window, WindowContext};
 use self::sdl2::render::{Texture, TextureCreator, WindowCanvas};
 // use game_of_life::{SQUARE_SIZE, PLAYGROUND_WIDTH, PLAYGROUND_HEIGHT};
 
+use ::domain::Point;
 use ::domain::terrain::Terrain;
+use ::domain::building::Building;
 
 use std::time::{Duration, Instant};
 
-use std::sync::{Mutex, Arc};
+use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
 
@@ -109,7 +111,6 @@ fn check_quit(event_pump : &mut EventPump) -> bool{
 }
 
 fn update_texture(pixels :&mut Vec<u8>, terrain : &Terrain, canvas : &mut WindowCanvas, texture : &mut Texture) {
-    let data = terrain.get_data_ref();
     let x_size = terrain.xsize;
     let y_size = terrain.ysize;
     let num_px = sdl2::pixels::PixelFormatEnum::ARGB8888.byte_size_of_pixels(x_size * y_size);
@@ -134,21 +135,22 @@ fn update_texture(pixels :&mut Vec<u8>, terrain : &Terrain, canvas : &mut Window
     texture.with_lock(None, |buffer: &mut [u8], _pitch: usize| {
         for idx in 0..(x_size * y_size) {
             let offset = idx * 4;
-            unsafe{
-            buffer[offset + 0] = *data.get_unchecked(idx) as u8; // b
-            buffer[offset + 1] = (*data.get_unchecked(idx)*100) as u8;//(data.get_unchecked(idx)*100_isize) as u8; // g
-            buffer[offset + 2] = *data.get_unchecked(idx) as u8; // r
+            let val = terrain.get_pt_val(&Point{x: (idx % x_size) as isize, y: (idx / x_size) as isize});
+            buffer[offset + 0] = val as u8; // b
+            buffer[offset + 1] = (val*100) as u8;//(data.get_unchecked(idx)*100_isize) as u8; // g
+            buffer[offset + 2] = val as u8; // r
             buffer[offset + 3] = 255_u8; // a (opaque)
-            }
         }
     }).unwrap();
 }
 
 
-pub fn spawn_graph_thread(pterrain : Arc<Mutex<Terrain>>, nb_pers : usize) -> JoinHandle<()> {
+/// Displays the ground floor of the Building until every Person has left it.
+pub fn spawn_graph_thread(pbuilding : Arc<Building>, nb_pers : usize) -> JoinHandle<()> {
 
 
     let graph_handle = thread::spawn(move || {
+        let pterrain = pbuilding.get_floor(0);
 
         let xsize;
         let ysize;
@@ -190,8 +192,8 @@ pub fn spawn_graph_thread(pterrain : Arc<Mutex<Terrain>>, nb_pers : usize) -> Jo
                     // graph update
                     let terrain = pterrain.lock().unwrap();
                     update_texture(&mut pixels, &terrain, &mut canvas, &mut texture);
-                    exited_count = terrain.get_exited_cnt().clone();
                 }
+                exited_count = pbuilding.get_left_cnt();
                 canvas.set_draw_color(Color::RGB(0, 0, 0));
                 canvas.clear();
                 canvas.copy(&texture,
@@ -211,7 +213,9 @@ pub fn spawn_graph_thread(pterrain : Arc<Mutex<Terrain>>, nb_pers : usize) -> Jo
 
 
 
-pub fn graph_loop(pterrain : Arc<Mutex<Terrain>>, nb_pers : usize) {
+/// Displays the ground floor of the Building until every Person has left it.
+pub fn graph_loop(pbuilding : Arc<Building>, nb_pers : usize) {
+        let pterrain = pbuilding.get_floor(0);
 
         let xsize;
         let ysize;
@@ -253,7 +257,6 @@ pub fn graph_loop(pterrain : Arc<Mutex<Terrain>>, nb_pers : usize) {
                     // graph update
                     let terrain = pterrain.lock().unwrap();
                     update_texture(&mut pixels, &terrain, &mut canvas, &mut texture);
-                    exited_count = terrain.get_exited_cnt();
 
                     canvas.set_draw_color(Color::RGB(0, 0, 0));
                     canvas.clear();
@@ -269,6 +272,7 @@ pub fn graph_loop(pterrain : Arc<Mutex<Terrain>>, nb_pers : usize) {
                         start = Instant::now();
                     }
                 }
+                exited_count = pbuilding.get_left_cnt();
 
             }
 
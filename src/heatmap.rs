@@ -0,0 +1,165 @@
+use domain::Point;
+use domain::terrain::Terrain;
+
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// colour of the obstacles in the PPM images
+const OBSTACLE: [u8; 3] = [64, 64, 64];
+
+// *****
+// LAYER
+// *****
+/// What a heatmap counts for each cell of a Terrain.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Layer {
+    /// ticks the cell was occupied by a Person
+    Occupancy,
+    /// moves into the cell refused because it was taken
+    Refusals,
+}
+
+impl Layer {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Layer::Occupancy => "occupancy",
+            Layer::Refusals => "refusals",
+        }
+    }
+}
+
+// ************
+// IMAGE FORMAT
+// ************
+/// Image formats of the heatmaps : grey levels, or colours from white to red with the obstacles in grey.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFormat {
+    Pgm,
+    Ppm,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            ImageFormat::Pgm => "pgm",
+            ImageFormat::Ppm => "ppm",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ImageFormat, String> {
+        match s.trim().to_lowercase().as_str() {
+            "pgm" => Ok(ImageFormat::Pgm),
+            "ppm" => Ok(ImageFormat::Ppm),
+            _ => Err(format!("\"{}\" is not a heatmap format, expected pgm or ppm", s)),
+        }
+    }
+}
+
+// *******
+// HEATMAP
+// *******
+/// A layer of a Terrain at the end of a run, read from the Terrain : only its largest count is kept.
+#[derive(Clone)]
+pub struct Heatmap<'a> {
+    pub layer: Layer,
+    pub xsize: usize,
+    pub ysize: usize,
+    terrain: &'a Terrain,
+    max: u32,
+}
+
+impl<'a> Heatmap<'a> {
+
+    pub fn from_terrain(terrain: &'a Terrain, layer: Layer) -> Heatmap<'a> {
+        let mut max = 0;
+        terrain.for_each_heat(|_, occupied, refused| {
+            max = cmp::max(max, match layer { Layer::Occupancy => occupied, Layer::Refusals => refused });
+        });
+        Heatmap { layer, xsize: terrain.xsize, ysize: terrain.ysize, terrain, max }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u32 {
+        let point = Point{x: x as isize, y: y as isize};
+        match self.layer {
+            Layer::Occupancy => self.terrain.get_occupied_ticks(&point),
+            Layer::Refusals => self.terrain.get_refused_moves(&point),
+        }
+    }
+
+    pub fn max(&self) -> u32 {
+        self.max
+    }
+
+    /// Writes the binary PGM or PPM image of the heatmap row by row : the largest count is the brightest
+    /// (or reddest) cell. The top row of the image is the top of the Terrain, as it is displayed.
+    pub fn write_image<W: Write>(&self, format: ImageFormat, out: &mut W) -> io::Result<()> {
+        let magic = match format { ImageFormat::Pgm => "P5", ImageFormat::Ppm => "P6" };
+        write!(out, "{}\n{} {}\n255\n", magic, self.xsize, self.ysize)?;
+        let mut row = Vec::with_capacity(self.xsize * 3);
+        for y in (0..self.ysize).rev() {
+            row.clear();
+            for x in 0..self.xsize {
+                let level = if self.max == 0 { 0 } else { (self.get(x, y) as u64 * 255 / self.max as u64) as u8 };
+                match format {
+                    ImageFormat::Pgm => row.push(level),
+                    ImageFormat::Ppm if self.terrain.get_pt_val(&Point{x: x as isize, y: y as isize}) == -1 =>
+                        row.extend_from_slice(&OBSTACLE),
+                    // white, yellow then red
+                    ImageFormat::Ppm => row.extend_from_slice(&[255, 255 - level / 2, 255 - level]),
+                }
+            }
+            out.write_all(&row)?;
+        }
+        Ok(())
+    }
+
+    /// The whole image, see `write_image`.
+    pub fn to_image(&self, format: ImageFormat) -> Vec<u8> {
+        let mut image = Vec::new();
+        self.write_image(format, &mut image).expect("Could not write the image to memory");
+        image
+    }
+
+    pub fn save(&self, path: &Path, format: ImageFormat) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_image(format, &mut out)?;
+        out.flush()
+    }
+}
+
+/// Writes both layers of the Terrain as CSV, one row per cell with a count (the other cells count nothing).
+pub fn write_csv<W: Write>(terrain: &Terrain, out: &mut W) -> io::Result<()> {
+    writeln!(out, "x,y,obstacle,occupied_ticks,refused_moves")?;
+    let mut result = Ok(());
+    terrain.for_each_heat(|point, occupied, refused| {
+        if result.is_ok() {
+            result = writeln!(out, "{},{},{},{},{}", point.x, point.y, (terrain.get_pt_val(point) == -1) as u8, occupied, refused);
+        }
+    });
+    result
+}
+
+/// Saves the heatmaps of a floor in the directory : `floor<n>_occupancy`, `floor<n>_refusals`
+/// images and `floor<n>_heatmap.csv`. Returns the files written.
+pub fn save_heatmaps(terrain: &Terrain, floor: usize, dir: &Path, format: ImageFormat) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for &layer in &[Layer::Occupancy, Layer::Refusals] {
+        let path = dir.join(format!("floor{}_{}.{}", floor, layer.name(), format.extension()));
+        Heatmap::from_terrain(terrain, layer).save(&path, format)?;
+        files.push(path);
+    }
+    let path = dir.join(format!("floor{}_heatmap.csv", floor));
+    let mut out = BufWriter::new(File::create(&path)?);
+    write_csv(terrain, &mut out)?;
+    out.flush()?;
+    files.push(path);
+    Ok(files)
+}
@@ -3,6 +3,11 @@ extern crate log;
 
 pub mod domain;
 pub mod statistics;
+pub mod lock;
+pub mod export;
+pub mod bench;
+pub mod chart;
+pub mod heatmap;
 #[cfg(feature="gui")]
 pub mod graphics;
 
@@ -12,7 +17,29 @@ mod tests {
     use domain::*;
     use domain::Point;
     use domain::terrain::Terrain;
-    use domain::person::Person;
+    use domain::person::{Person, Lifecycle};
+    use domain::events::LayoutEvent;
+    use domain::hazard::HazardRules;
+    use domain::building::{Building, Stair};
+    use domain::strategy::{StrategyKind, FieldFollowing, GreedyToAzimuth, MovementStrategy, Decision};
+    use domain::field::FIELD_RADIUS;
+    use domain::exit::ExitChoice;
+    use domain::population::{AgentClass, parse_population, class_of};
+    use domain::perception::{Knowledge, Sign};
+    use domain::report::{ExitReport, SteadyStateReport, EvacuationReport, OutcomeReport};
+    use domain::arrivals::{Source, Arrivals};
+    use domain::config::SimConfig;
+    use export::{Exporter, Format, RunSetup, AggregateRow};
+    use chart::bench_charts;
+    use heatmap::{Heatmap, Layer, ImageFormat, save_heatmaps};
+    use bench::{BenchPlan, parse_list};
+    use statistics::{PerfResult, PerfSummary};
+    use std::time::Duration;
+    use std::{env, fs, process, slice};
+    use std::sync::Arc;
+    use std::thread;
+    use lock::{InstrumentedMutex, LockStats};
+    use std::path::Path;
 
     #[test]
     fn declare_small_terrain() {
@@ -101,14 +128,718 @@ mod tests {
             moves = terrain.list_possible_moves(&userX.position);
             println!("possible moves : {:?}", moves);
             //#[derive(Debug)]
-            let good_point = userX.choose_best_move(&moves);
+            let good_point = userX.choose_best_move(&terrain, &moves);
             userX.move_to(&mut terrain, &good_point);
             println!("moving to : {}", good_point);
         }
     }
 
 
-}
+    #[test]
+    fn cost_map_sets_cell_costs() {
+        let mut terrain: Terrain = Terrain::new(XSIZE, YSIZE);
+        terrain.apply_cost_map("3.\n.9x");
+        assert_eq!(terrain.get_pt_cost(&Point{x:0, y:YSIZE as isize - 1}), 3);
+        assert_eq!(terrain.get_pt_cost(&Point{x:1, y:YSIZE as isize - 1}), 1);
+        assert_eq!(terrain.get_pt_cost(&Point{x:1, y:YSIZE as isize - 2}), 9);
+        assert_eq!(terrain.get_pt_cost(&Point{x:2, y:YSIZE as isize - 2}), 1);
+    }
+
+    #[test]
+    fn slow_cell_needs_several_turns() {
+        let mut terrain: Terrain = Terrain::new(XSIZE, YSIZE);
+        terrain.set_pt_cost(&Point{x:10, y:10}, 3);
+
+        let mut user = Person::new(51, Point{x:10, y:10} );
+        user.place_on_terrain(&mut terrain);
+
+        user.look_and_move(&mut terrain);
+        user.look_and_move(&mut terrain);
+        assert_eq!(user.position, Point{x:10, y:10});
+        user.look_and_move(&mut terrain);
+        assert!(user.position != Point{x:10, y:10});
+    }
+
+
+    #[test]
+    fn door_closes_and_opens_on_timeline() {
+        let mut terrain: Terrain = Terrain::new(XSIZE, YSIZE);
+        let door = Point{x:5, y:5};
+        terrain.schedule_event(2, LayoutEvent::Block(door, door));
+        terrain.schedule_event(4, LayoutEvent::Clear(door, door));
+        let version = terrain.get_layout_version();
+
+        terrain.advance_tick();
+        assert_eq!(terrain.get_pt_val(&door), 0);
+        terrain.advance_tick();
+        assert_eq!(terrain.get_pt_val(&door), -1);
+        assert!(terrain.get_layout_version() != version);
+        assert!(!terrain.list_possible_moves(&Point{x:4, y:4}).contains(&door));
+        terrain.advance_tick();
+        terrain.advance_tick();
+        assert_eq!(terrain.get_pt_val(&door), 0);
+    }
+
+    #[test]
+    fn barrier_waits_for_occupied_cell_to_be_freed() {
+        let mut terrain: Terrain = Terrain::new(XSIZE, YSIZE);
+        let mut user = Person::new(51, Point{x:10, y:10} );
+        user.place_on_terrain(&mut terrain);
+        terrain.schedule_event(1, LayoutEvent::Block(Point{x:10, y:9}, Point{x:10, y:10}));
+
+        terrain.advance_tick();
+        assert_eq!(terrain.get_pt_val(&Point{x:10, y:9}), -1);
+        assert_eq!(terrain.get_pt_val(&Point{x:10, y:10}), 51);
+
+        user.look_and_move(&mut terrain); // ends the tick : only one Person on the Terrain
+        assert!(user.position != Point{x:10, y:10});
+        assert_eq!(terrain.get_pt_val(&Point{x:10, y:10}), -1);
+    }
+
+
+    #[test]
+    fn hazard_spreads_and_makes_casualties() {
+        let mut terrain: Terrain = Terrain::new(XSIZE, YSIZE);
+        terrain.set_hazard_rules(HazardRules { spread_probability: 1., growth: 100, ..HazardRules::default() });
+        terrain.ignite(&Point{x:10, y:10}, 100);
+
+        let mut user = Person::new(51, Point{x:11, y:10} );
+        user.place_on_terrain(&mut terrain);
+        terrain.add_obstacle(Point{x:8, y:8}, Point{x:8, y:12});
+
+        terrain.advance_tick();
+        assert_eq!(terrain.get_hazard(&Point{x:10, y:10}), 200);
+        assert_eq!(terrain.get_hazard(&Point{x:11, y:10}), 100);
+        assert_eq!(terrain.get_hazard(&Point{x:8, y:10}), 0); // walls stop the spread
+        assert!(!terrain.list_possible_moves(&user.position).contains(&Point{x:10, y:10}));
+
+        terrain.advance_tick();
+        user.look_and_move(&mut terrain);
+        assert!(user.is_casualty() && user.has_left());
+        assert_eq!(user.exposure_turns, 1);
+        assert_eq!(terrain.get_casualties_cnt(), 1);
+        assert_eq!(terrain.get_exited_cnt(), 0);
+        assert_eq!(terrain.get_left_cnt(), 1);
+    }
+
+
+    #[test]
+    fn person_walks_down_the_stairs_of_a_building() {
+        let stair = Stair { upper_floor: 1, upper_point: Point{x:11, y:10},
+            lower_floor: 0, lower_point: Point{x:20, y:20}, delay: 2, capacity: 1 };
+        let building = Building::new(vec![Terrain::new(XSIZE, YSIZE), Terrain::new(XSIZE, YSIZE)], vec![stair]);
+
+        let mut user = Person::new(51, Point{x:10, y:10} );
+        user.floor = 1;
+        user.place_on_terrain(&mut building.get_floor(1).lock().unwrap());
+
+        building.look_and_move(&mut user); // reaches the stair
+        assert_eq!(user.stair, Some(0));
+        assert_eq!(user.get_state(), Lifecycle::InStair);
+        assert!(!user.has_left() && user.left_tick.is_none());
+        assert_eq!(building.get_floor(1).lock().unwrap().get_active_cnt(), 0);
+        assert_eq!(building.get_floor(1).lock().unwrap().get_exited_cnt(), 0);
+        assert_eq!(building.get_floor(1).lock().unwrap().get_stair_cnt(), 1);
+
+        building.look_and_move(&mut user);
+        assert_eq!(user.stair, Some(0));
+        building.look_and_move(&mut user); // arrives on the ground floor
+        assert_eq!(user.stair, None);
+        assert_eq!(user.floor, 0);
+        assert_eq!(building.get_floor(0).lock().unwrap().get_pt_val(&Point{x:20, y:20}), 51);
+        assert_eq!(building.get_left_cnt(), 0);
+    }
+
+    #[test]
+    fn time_to_escape_is_counted_across_floors() {
+        let stair = Stair { upper_floor: 1, upper_point: Point{x:15, y:15},
+            lower_floor: 0, lower_point: Point{x:4, y:16}, delay: 2, capacity: 1 };
+        let building = Building::new(vec![Terrain::new(20, 20), Terrain::new(20, 20)], vec![stair]);
+        let mut user = Person::new(51, Point{x:2, y:2} );
+        user.floor = 1;
+        user.place_on_terrain(&mut building.get_floor(1).lock().unwrap());
+
+        for _ in 0..100 {
+            if !user.has_left() {
+                building.look_and_move(&mut user);
+            }
+        }
+        assert!(user.has_escaped());
+        // the ground floor was idle until the Person came down : it caught up with the upper floor
+        let upper = building.get_floor(1).lock().unwrap().get_tick();
+        let ground = building.get_floor(0).lock().unwrap().get_tick();
+        assert!(upper >= 13 && ground > upper);
+        assert_eq!(user.left_tick, Some(ground - 1)); // the ground floor ticked at the end of the last turn
+        assert_eq!(user.time_to_escape(), user.left_tick);
+    }
+
+
+    #[test]
+    fn large_terrain_is_stored_sparsely() {
+        let mut terrain: Terrain = Terrain::new(20000, 20000);
+        for _ in 0..1000 {
+            let pt : Point = terrain.get_random_free_point()
+                .expect("not enough free positions");
+            terrain.place_person(&pt, 51);
+        }
+        assert_eq!(terrain.count_persons_in_terrain(), 1000);
+        assert!(terrain.memory_size() < 64 * 1024 * 1024);
+
+        // the exit field is only computed around the exit, farther cells get an estimate
+        assert_eq!(terrain.get_exit_distance(&Point{x:5, y:19990}), 8.);
+        let far = terrain.get_exit_distance(&Point{x:10000, y:10000});
+        assert!(far.is_finite() && far > FIELD_RADIUS);
+        assert!(terrain.get_exit_distance(&Point{x:9999, y:10001}) < far);
+        assert!(terrain.memory_size() < 64 * 1024 * 1024);
+    }
+
+    #[test]
+    fn field_following_walks_around_a_wall() {
+        let mut terrain: Terrain = Terrain::new(20, 20);
+        for x in 0..16 {
+            terrain.set_pt_val(&Point{x, y:15}, -1);
+        }
+        let mut user = Person::new(51, Point{x:2, y:10} );
+        user.place_on_terrain(&mut terrain);
+        user.set_strategy(Arc::new(FieldFollowing));
+
+        // the field goes through the gap at the end of the wall
+        assert!(terrain.get_exit_distance(&Point{x:2, y:14}) > 20.);
+        for _ in 0..100 {
+            user.look_and_move(&mut terrain);
+        }
+        assert!(user.has_escaped());
+        assert_eq!("field".parse::<StrategyKind>(), Ok(StrategyKind::FieldFollowing));
+        assert!("teleport".parse::<StrategyKind>().is_err());
+    }
+
+    #[test]
+    fn field_following_person_walks_away_from_a_hazard() {
+        let mut terrain: Terrain = Terrain::new(20, 20);
+        terrain.set_hazard_rules(HazardRules { spread_probability: 0., growth: 0, ..HazardRules::default() });
+        terrain.ignite(&Point{x:9, y:11}, 100); // on the way to the exit
+        let mut user = Person::new(51, Point{x:10, y:10} );
+        user.place_on_terrain(&mut terrain);
+        user.set_strategy(Arc::new(FieldFollowing));
+        user.hazard_aversion = 10.;
+
+        // every cell closer to the exit is next to the hazard, as the current one : the Person does not wait there
+        user.look_and_move(&mut terrain);
+        assert_eq!(user.get_state(), Lifecycle::Moving);
+        assert!(user.position != Point{x:10, y:10});
+    }
+
+    #[test]
+    fn person_leaves_a_congested_exit() {
+        let mut terrain: Terrain = Terrain::new(20, 20);
+        terrain.add_exit_point(Point{x:18, y:0});
+        terrain.add_exit_point(Point{x:19, y:0});
+        assert_eq!(terrain.get_exits().len(), 2);
+        assert_eq!(terrain.get_exits()[1].width(), 2);
+
+        let mut user = Person::new(51, Point{x:3, y:10} );
+        user.place_on_terrain(&mut terrain);
+        user.exit_choice = ExitChoice::Nearest;
+        user.exit_switch_margin = 1.;
+        assert_eq!(user.target_exit(&terrain).map(|e| e.id), Some(0));
+
+        // a crowd gathers in front of the nearest exit
+        for x in 2..8 {
+            for y in 12..20 {
+                terrain.place_person(&Point{x, y}, 100 + (x * 20 + y) as usize);
+            }
+        }
+        assert_eq!(user.target_exit(&terrain).map(|e| e.id), Some(0)); // not reviewed yet
+        for _ in 0..10 {
+            terrain.advance_tick();
+        }
+        assert_eq!(user.target_exit(&terrain).map(|e| e.id), Some(1));
+    }
+
+    #[test]
+    fn persons_are_not_placed_on_exits() {
+        let mut terrain: Terrain = Terrain::new(4, 4);
+        for x in 0..4 {
+            for y in 0..4 {
+                if (x, y) != (3, 3) {
+                    terrain.add_exit_point(Point{x, y});
+                }
+            }
+        }
+        for seed in 0..20 {
+            terrain.set_seed(seed);
+            assert_eq!(terrain.get_random_free_area(1), Some(Point{x:3, y:3}));
+        }
+    }
+
+    #[test]
+    fn wheelchair_takes_four_cells_to_the_exit() {
+        let mut terrain: Terrain = Terrain::new(20, 20);
+        let mut user = Person::new(51, Point{x:10, y:10} );
+        AgentClass::builtin("wheelchair").unwrap().apply(1, &mut user);
+        user.place_on_terrain(&mut terrain);
+        assert_eq!(terrain.get_pt_val(&Point{x:11, y:11}), 51);
+        assert_eq!(terrain.count_persons_in_terrain(), 4);
+
+        // a one cell gap is too narrow, a two cells one is fine
+        let mut narrow: Terrain = Terrain::new(20, 20);
+        narrow.add_obstacle(Point{x:8, y:0}, Point{x:8, y:18});
+        assert!(narrow.get_footprint_exit_distance(&Point{x:10, y:10}, 2).is_infinite());
+        assert!(narrow.get_footprint_exit_distance(&Point{x:10, y:10}, 1).is_finite());
+        terrain.add_obstacle(Point{x:8, y:0}, Point{x:8, y:17});
+        assert!(terrain.get_footprint_exit_distance(&Point{x:10, y:10}, 2).is_finite());
+
+        let mut ticks = 0;
+        while !user.has_left() && ticks < 500 {
+            user.look_and_move(&mut terrain);
+            ticks += 1;
+        }
+        assert!(user.has_escaped());
+        assert_eq!(terrain.count_persons_in_terrain(), 0);
+    }
+
+    #[test]
+    fn group_leaves_together() {
+        let mut terrain: Terrain = Terrain::new(20, 20);
+        let mut leader = Person::new(51, Point{x:1, y:17} );
+        let mut straggler = Person::new(52, Point{x:6, y:12} );
+        for pers in [&mut leader, &mut straggler].iter_mut() {
+            pers.group = Some(0);
+            pers.place_on_terrain(&mut terrain);
+        }
+        straggler.speed = 0.5;
+        let mut persons = vec![leader, straggler];
+        slow_down_groups(&mut persons);
+        assert_eq!(persons[0].speed, 0.5);
+        persons[0].speed = 1.;
+
+        // the leader waits at the exit for the straggler
+        persons[0].look_and_move(&mut terrain);
+        assert!(!persons[0].has_left());
+        for _ in 0..50 {
+            for pers in persons.iter_mut().filter(|p| !p.has_left()) {
+                pers.look_and_move(&mut terrain);
+            }
+        }
+        assert!(persons.iter().all(|p| p.has_escaped()));
+        let (first, last) = (persons[0].left_tick.unwrap(), persons[1].left_tick.unwrap());
+        assert!(first >= 5 && last - first <= 5);
+    }
 
+    #[test]
+    fn group_with_a_wheelchair_starts_on_the_ground_floor() {
+        let config = SimConfig { floors: 3, group_size: 3, population: parse_population("walker:0.7,wheelchair:0.3").unwrap(),
+            ..SimConfig::default() };
+        let (_, persons) = initialize_building_and_users(&config, 24, 40, 20);
+        assert!(persons.iter().any(|p| p.floor > 0));
+        for group in persons.chunks(3) {
+            let leader = &group[0];
+            for member in group {
+                assert_eq!(member.floor, leader.floor);
+                assert!((member.position.x - leader.position.x).abs() < 5 && (member.position.y - leader.position.y).abs() < 5);
+                if member.footprint > 1 {
+                    assert_eq!(member.floor, 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn azimuth_strategy_keeps_the_historical_moves() {
+        let mut terrain: Terrain = Terrain::new(XSIZE, YSIZE);
+        terrain.set_azimuth(Point{x: 10, y: 15});
+        let azimuth = *terrain.get_azimuth();
+        let mut user = Person::new(51, Point{x: azimuth.x, y: azimuth.y - 5} );
+        // on either side of the way to the azimuth, at the same distance, then farther
+        let moves = vec![Point{x: azimuth.x + 1, y: azimuth.y - 4}, Point{x: azimuth.x - 1, y: azimuth.y - 4},
+                         Point{x: azimuth.x - 1, y: azimuth.y - 5}, Point{x: azimuth.x, y: azimuth.y - 6}];
+        let square_distance = |m: &Point| (m.x - azimuth.x).pow(2) + (m.y - azimuth.y).pow(2);
+
+        // the first of the closest moves, whatever their order
+        for shift in 0..moves.len() {
+            let mut shifted = moves.clone();
+            shifted.rotate_left(shift);
+            let closest = *shifted.iter().min_by_key(|m| square_distance(m)).unwrap();
+            for _ in 0..10 {
+                assert_eq!(GreedyToAzimuth.decide(&user, &shifted, &terrain), Decision::MoveTo(closest));
+            }
+        }
+        assert_eq!(GreedyToAzimuth.decide(&user, &[], &terrain), Decision::Stay);
+
+        // with a memory, the ties go either way
+        user.memory_size = 16;
+        let decisions: Vec<Decision> = (0..20).map(|_| GreedyToAzimuth.decide(&user, &moves, &terrain)).collect();
+        assert!(decisions.contains(&Decision::MoveTo(moves[0])) && decisions.contains(&Decision::MoveTo(moves[1])));
+    }
+
+    #[test]
+    fn memory_gets_persons_around_a_wall() {
+        let mut escaped = Vec::new();
+        for &memory in &[0, 16] {
+            let mut terrain: Terrain = Terrain::new(20, 20);
+            terrain.add_obstacle(Point{x:0, y:15}, Point{x:5, y:15});
+            let mut user = Person::new(51, Point{x:3, y:10} );
+            user.memory_size = memory;
+            user.place_on_terrain(&mut terrain);
+            for _ in 0..100 {
+                user.look_and_move(&mut terrain);
+            }
+            escaped.push(user.has_escaped());
+        }
+        // without memory, the Person bounces in the corner of the wall
+        assert_eq!(escaped, vec![false, true]);
+    }
+
+    #[test]
+    fn lifecycle_records_blocked_time_and_escape() {
+        let mut terrain: Terrain = Terrain::new(20, 20);
+        let mut user = Person::new(51, Point{x:10, y:10} );
+        assert_eq!(user.get_state(), Lifecycle::Unplaced);
+        user.place_on_terrain(&mut terrain);
+        assert_eq!(user.get_state(), Lifecycle::Waiting);
+
+        // walled in for 3 ticks
+        let around: Vec<Point> = Point{x:9, y:9}.footprint(3).into_iter()
+            .filter(|p| *p != user.position)
+            .collect();
+        for pt in &around {
+            terrain.set_pt_val(pt, -1);
+        }
+        for _ in 0..3 {
+            user.look_and_move(&mut terrain);
+            assert_eq!(user.get_state(), Lifecycle::Blocked);
+        }
+        for pt in &around {
+            terrain.set_pt_val(pt, 0);
+        }
+        user.look_and_move(&mut terrain);
+        assert_eq!(user.get_state(), Lifecycle::Moving);
+        assert_eq!(user.blocked_ticks, 3);
+
+        while !user.has_left() {
+            user.look_and_move(&mut terrain);
+        }
+        assert!(user.has_escaped() && !user.is_casualty());
+        assert_eq!(user.time_to_escape(), user.left_tick);
+        assert_eq!(user.moves_cnt, 9);
+    }
+
+    #[test]
+    fn face_to_face_persons_swap() {
+        let mut results = Vec::new();
+        for &swap in &[false, true] {
+            // a corridor with an exit at each end
+            let mut terrain: Terrain = Terrain::new(20, 20);
+            terrain.clear_exit_points();
+            terrain.add_exit_point(Point{x:0, y:10});
+            terrain.add_exit_point(Point{x:19, y:10});
+            terrain.add_obstacle(Point{x:0, y:9}, Point{x:19, y:9});
+            terrain.add_obstacle(Point{x:0, y:11}, Point{x:19, y:11});
+            let mut persons = Vec::new();
+            // ids 3 and 2 are assigned the right and left exits, they stand in each other's way
+            for &(id, x) in &[(3, 8), (2, 9)] {
+                let mut pers = Person::new(id, Point{x, y:10});
+                pers.set_strategy(StrategyKind::GreedyToTargetExit.build());
+                pers.exit_choice = ExitChoice::Assigned;
+                pers.patience = 255;
+                pers.swap = swap;
+                pers.place_on_terrain(&mut terrain);
+                persons.push(pers);
+            }
+            for _ in 0..50 {
+                for pers in persons.iter_mut() {
+                    pers.look_and_move(&mut terrain);
+                }
+            }
+            results.push((persons.iter().all(|p| p.has_escaped()), terrain.get_swaps_cnt()));
+        }
+        assert_eq!(results, vec![(false, 0), (true, 1)]);
+    }
+
+    #[test]
+    fn sign_shows_the_way_to_an_unseen_exit() {
+        // the exit is in the top left corner, the Person starts far from it, behind a wall
+        let mut terrain: Terrain = Terrain::new(40, 40);
+        terrain.add_obstacle(Point{x:20, y:0}, Point{x:20, y:30});
+        terrain.add_sign("30,32:5,35".parse::<Sign>().unwrap());
+        let mut user = Person::new(51, Point{x:30, y:5} );
+        user.knowledge = Some(Knowledge::new(6.));
+        user.place_on_terrain(&mut terrain);
+
+        user.look_and_move(&mut terrain);
+        assert!(!user.knowledge.as_ref().unwrap().knows_exit());
+        for _ in 0..300 {
+            user.look_and_move(&mut terrain);
+        }
+        assert!(user.has_escaped());
+        assert!(user.knowledge.as_ref().unwrap().knows_exit());
+        assert!(terrain.is_visible(&Point{x:10, y:38}, &Point{x:0, y:39}));
+        assert!(!terrain.is_visible(&Point{x:15, y:10}, &Point{x:25, y:10}));
+    }
+
+    #[test]
+    fn panicked_person_follows_the_crowd() {
+        let mut moves = Vec::new();
+        for &panic in &[0., 1.] {
+            let mut terrain: Terrain = Terrain::new(20, 20);
+            // two neighbours walk to the right, away from the exit (top left corner)
+            for &(id, y) in &[(61, 10), (71, 11)] {
+                terrain.place_person(&Point{x:11, y}, id);
+                terrain.move_src_to_dst(&Point{x:11, y}, &Point{x:12, y});
+            }
+            let mut user = Person::new(51, Point{x:10, y:10} );
+            user.set_strategy(StrategyKind::Herding.build());
+            user.panic = panic;
+            user.place_on_terrain(&mut terrain);
+            user.look_and_move(&mut terrain);
+            moves.push(user.position.x - 10);
+        }
+        assert_eq!(moves, vec![-1, 1]);
+
+        let report = ExitReport::from_usage(&[30, 10]);
+        assert_eq!(report.imbalance, 0.5);
+        assert_eq!("walker:1:0.7".parse::<AgentClass>().unwrap().panic, 0.7);
+    }
+
+    #[test]
+    fn arrivals_reach_a_steady_state() {
+        let source = "15,2:17,4:0.25".parse::<Source>().unwrap();
+        assert_eq!((0..8).map(|t| source.due(t)).sum::<usize>(), 2);
 
+        let config = SimConfig { sources: vec!["15,2:17,4:0.5".parse::<Source>().unwrap()], ..SimConfig::default() };
+        let building = Building::new_single_floor(Terrain::new(20, 20));
+        let mut arrivals = Arrivals::new(&config, 0);
+        let mut persons: Vec<Person> = Vec::new();
+        while building.get_tick() < 400 {
+            persons.extend(arrivals.spawn(&config, &building));
+            building.advance_idle_floors();
+            for pers in persons.iter_mut() {
+                if !pers.has_left() {
+                    building.look_and_move(pers);
+                }
+            }
+        }
+        let report = SteadyStateReport::from_building(&building, None, &arrivals);
+        assert!(arrivals.arrived >= 195 && report.warmup > 0);
+        assert!(report.mean_occupancy > 5. && report.mean_occupancy < 50.);
+        assert!((report.throughput - 0.5).abs() < 0.05);
+    }
 
+    #[test]
+    fn population_mix_follows_the_shares() {
+        let classes = parse_population("walker:0.75,elderly:0.25").unwrap();
+        assert_eq!(classes[1].speed, 0.5);
+        let elderly = (0..100).filter(|&rank| class_of(rank, &classes) == 1).count();
+        assert!((23..=27).contains(&elderly));
+        assert!(parse_population("cart:1:1:0:3").is_ok());
+        assert!(parse_population("ghost:1").is_err());
+    }
+
+    #[test]
+    fn runs_are_exported_with_their_setup_and_outcome() {
+        let mut config = SimConfig { xsize: 40, ysize: 20, ..SimConfig::default() };
+        let (terrain, _) = initialize_terrain_and_users(&config, 8, 40, 20);
+        config.seed = 7;
+        let (other, _) = initialize_terrain_and_users(&config, 8, 40, 20);
+        assert!((0..40).any(|x| (0..20).any(|y| terrain.get_pt_val(&Point{x, y}) != other.get_pt_val(&Point{x, y}))));
+
+        let setup = RunSetup { scenario: 2, persons: 8, xsize: 40, ysize: 20, seed: 7, config: 0 };
+        let report = EvacuationReport::from_terrain(&other);
+        let mut csv = Exporter::new(Format::Csv, Vec::new()).unwrap();
+        csv.write_run(&setup, 3, None, &report).unwrap();
+        let csv = String::from_utf8(csv.into_inner()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+        assert!(lines[1].starts_with("run,2,8,40,20,7,0,3,1,,"));
+
+        let mut jsonl = Exporter::new(Format::Jsonl, Vec::new()).unwrap();
+        jsonl.write_run(&setup, 3, None, &report).unwrap();
+        let jsonl = String::from_utf8(jsonl.into_inner()).unwrap();
+        assert!(jsonl.starts_with("{\"kind\":\"run\",\"scenario\":2,"));
+        assert!(jsonl.contains("\"time\":null") && jsonl.contains("\"escaped\":0"));
+        assert_eq!(Format::from_path(Path::new("results.csv")), Some(Format::Csv));
+
+        // the outcome of a run made in a child process goes back to the parent as a record
+        let mut report = report;
+        report.exit_usage = vec![3, 0, 5];
+        report.steady = Some(SteadyStateReport { ticks: 100, warmup: 10, mean_occupancy: 12.5, throughput: 0.25, arrived: 40, waiting: 2 });
+        let back = report.to_record().parse::<EvacuationReport>().unwrap();
+        assert_eq!(back.exit_usage, report.exit_usage);
+        assert_eq!(back.steady.unwrap().mean_occupancy, 12.5);
+        assert_eq!(back.to_record(), report.to_record());
+    }
+
+    #[test]
+    fn interrupted_bench_resumes_with_the_missing_configurations() {
+        assert_eq!(parse_list("2..4,8").unwrap(), vec![2, 3, 4, 8]);
+        assert!(parse_list("4..2").is_err() && parse_list("2..x").is_err());
+
+        let plan = BenchPlan::new("2..3", "0,2").unwrap();
+        assert!(BenchPlan::new("2..3", "0..2").is_err());
+        let path = env::temp_dir().join(format!("progconc_bench_{}.csv", process::id()));
+        let _ = fs::remove_file(&path);
+        let (mut exporter, done) = Exporter::resume(&path, 5).unwrap();
+        assert!(done.is_empty());
+        let report = EvacuationReport::default();
+        let pending = plan.pending(40, 20, 1, 5, &done);
+        assert_eq!(pending.len(), 4);
+        let summary = PerfSummary::new(&[PerfResult { time: Duration::from_millis(1), utime: Duration::from_millis(1),
+            ..PerfResult::default() }], 0, &[]);
+        // the first configuration completes, the second one is interrupted after a run
+        exporter.write_run(&pending[0].1, 0, Some(&summary.mean), &report).unwrap();
+        exporter.write_aggregate(&pending[0].1, &summary, slice::from_ref(&report)).unwrap();
+        exporter.write_run(&pending[1].1, 0, Some(&summary.mean), &report).unwrap();
+        drop(exporter);
+
+        // rows measured with other options are not mixed with the new ones
+        assert!(Exporter::resume(&path, 6).is_err());
+        let (_, done) = Exporter::resume(&path, 5).unwrap();
+        assert_eq!(done, vec![pending[0].1]);
+        assert_eq!(plan.pending(40, 20, 1, 5, &done)[0], pending[1]);
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 3); // header, run and aggregate of the completed configuration
+        assert!(!path.with_extension("csv.resume").exists()); // the rows kept were moved over the file
+        fs::remove_file(&path).unwrap();
+
+        // the values of a json row are found by their keys, not by their text
+        let path = path.with_extension("jsonl");
+        let mut jsonl = Exporter::new(Format::Jsonl, Vec::new()).unwrap();
+        jsonl.write_aggregate(&pending[2].1, &summary, slice::from_ref(&report)).unwrap();
+        let row = String::from_utf8(jsonl.into_inner()).unwrap().replacen("{", "{\"note\":\"a, \\\"seed\\\":9}\",", 1);
+        fs::write(&path, row).unwrap();
+        let (_, done) = Exporter::resume(&path, 5).unwrap();
+        assert_eq!(done, vec![pending[2].1]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bench_charts_draw_one_line_per_scenario() {
+        let row = |scenario: usize, persons: usize, time: f64| AggregateRow {
+            setup: RunSetup { scenario, persons, xsize: 40, ysize: 20, seed: 1, config: 0 },
+            time, time_stddev: time / 10., utime: time, utime_stddev: 0., stime: 0., stime_stddev: 0., maxrss: 2048.,
+        };
+        let aggregates = vec![row(0, 4, 0.5), row(0, 8, 2.), row(2, 8, 0.1), row(2, 4, 0.05)];
+        let charts = bench_charts(&aggregates, true, true, true);
+        assert_eq!(charts.iter().map(|c| c.0.as_str()).collect::<Vec<_>>(),
+                   vec!["chart_time.svg", "chart_utime.svg", "chart_stime.svg", "chart_mem.svg"]);
+        let time = &charts[0].1;
+        assert_eq!(time.series.len(), 2);
+        assert_eq!(time.series[1].points, vec![(4., 0.05, 0.005), (8., 0.1, 0.01)]);
+
+        let svg = time.to_svg();
+        assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert_eq!(svg.matches("<circle").count(), 4);
+        assert!(svg.contains("scenario 2") && svg.contains("time (s) (log)"));
+    }
+
+    #[test]
+    fn instrumented_lock_records_waits_and_holds() {
+        let lock = Arc::new(InstrumentedMutex::new(0));
+        let guard = lock.lock().unwrap();
+        let waiters: Vec<_> = (0..2).map(|_| {
+            let lock = lock.clone();
+            thread::spawn(move || { *lock.lock().unwrap() += 1; })
+        }).collect();
+        thread::sleep(Duration::from_millis(50)); // the waiters are blocked meanwhile
+        drop(guard);
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+
+        let stats = lock.stats();
+        assert_eq!(*lock.lock().unwrap(), 2);
+        assert_eq!(stats.acquisitions, 3);
+        assert_eq!(stats.contended, 2);
+        assert_eq!(stats.contending_threads, 2);
+        assert!(stats.max_wait >= Duration::from_millis(10) && stats.wait >= stats.max_wait);
+        assert!(stats.hold >= Duration::from_millis(20));
+
+        let building = Building::new_single_floor(Terrain::new(10, 10));
+        building.get_floor(0).lock().unwrap().advance_tick();
+        assert_eq!(building.get_lock_stats().acquisitions, 1);
+        building.reset_lock_stats();
+        assert_eq!(building.get_lock_stats(), LockStats::default());
+    }
+
+    #[test]
+    fn outcome_sums_up_the_persons_after_the_run() {
+        let mut terrain: Terrain = Terrain::new(20, 20);
+        let mut persons: Vec<Person> = (0..3).map(|i| Person::new(51 + i, Point{x: 10 + 2 * i as isize, y: 10})).collect();
+        for pers in persons.iter_mut() {
+            pers.place_on_terrain(&mut terrain);
+        }
+        while persons.iter().any(|p| !p.has_left()) {
+            for pers in persons.iter_mut() {
+                pers.look_and_move(&mut terrain);
+            }
+        }
+
+        let outcome = OutcomeReport::from_persons(&persons);
+        let last = persons.iter().filter_map(|p| p.left_tick).max().unwrap();
+        assert_eq!(outcome.evacuation_ticks, last);
+        assert_eq!(outcome.escape_curve.len() as u64, last + 1);
+        assert_eq!(outcome.escape_curve.iter().sum::<usize>(), 3);
+        let mut times: Vec<u64> = persons.iter().filter_map(|p| p.time_to_escape()).collect();
+        times.sort();
+        assert_eq!(outcome.median_time_to_escape, times[1] as f32);
+        assert!(outcome.p95_time_to_escape > times[1] as f32 && outcome.p95_time_to_escape <= times[2] as f32);
+        // diagonal moves are longer than straight ones
+        assert!(outcome.distance > persons.iter().map(|p| p.moves_cnt).sum::<u64>() as f32);
+
+        let mut report = EvacuationReport::from_terrain(&terrain);
+        report.outcome = outcome;
+        let back = report.to_record().parse::<EvacuationReport>().unwrap();
+        assert_eq!(back.outcome, report.outcome);
+
+        // the escape curve is the last column of the exports
+        let setup = RunSetup { scenario: 2, persons: 3, xsize: 20, ysize: 20, seed: 1, config: 0 };
+        let curve: Vec<String> = report.outcome.escape_curve.iter().map(|c| c.to_string()).collect();
+        let mut csv = Exporter::new(Format::Csv, Vec::new()).unwrap();
+        csv.write_run(&setup, 0, None, &report).unwrap();
+        let csv = String::from_utf8(csv.into_inner()).unwrap();
+        assert!(csv.lines().nth(1).unwrap().ends_with(&format!(",{}", curve.join(";"))));
+        let mut jsonl = Exporter::new(Format::Jsonl, Vec::new()).unwrap();
+        jsonl.write_run(&setup, 0, None, &report).unwrap();
+        let jsonl = String::from_utf8(jsonl.into_inner()).unwrap();
+        assert!(jsonl.contains(&format!("\"escape_curve\":[{}]}}", curve.join(","))));
+    }
+
+    #[test]
+    fn heatmaps_show_occupied_and_refused_cells() {
+        let mut terrain: Terrain = Terrain::new(10, 10);
+        terrain.place_person(&Point{x:5, y:5}, 51);
+        terrain.place_person(&Point{x:6, y:6}, 52);
+        for _ in 0..3 {
+            terrain.advance_tick();
+        }
+        assert!(terrain.move_src_to_dst(&Point{x:5, y:5}, &Point{x:5, y:6}).is_some());
+        assert!(terrain.move_src_to_dst(&Point{x:6, y:6}, &Point{x:5, y:6}).is_none());
+        terrain.advance_tick();
+        terrain.advance_tick();
+        assert_eq!(terrain.get_occupied_ticks(&Point{x:5, y:5}), 3);
+        assert_eq!(terrain.get_occupied_ticks(&Point{x:5, y:6}), 2);
+        assert_eq!(terrain.get_occupied_ticks(&Point{x:6, y:6}), 5);
+        assert_eq!(terrain.get_refused_moves(&Point{x:5, y:6}), 1);
+
+        let occupancy = Heatmap::from_terrain(&terrain, Layer::Occupancy);
+        assert_eq!(occupancy.max(), 5);
+        let pgm = occupancy.to_image(ImageFormat::Pgm);
+        let header = "P5\n10 10\n255\n".len();
+        assert!(pgm.starts_with(b"P5\n10 10\n255\n"));
+        assert_eq!(pgm.len(), header + 100);
+        assert_eq!(pgm[header + (9 - 6) * 10 + 6], 255); // the top row is drawn first
+        assert_eq!(pgm[header + (9 - 5) * 10 + 5], 153); // 3 / 5 of the brightest
+        assert_eq!(Heatmap::from_terrain(&terrain, Layer::Refusals).to_image(ImageFormat::Ppm).len(), header + 300);
+
+        let dir = env::temp_dir().join(format!("progconc_heatmaps_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = save_heatmaps(&terrain, 0, &dir, ImageFormat::Ppm).unwrap();
+        assert_eq!(files.len(), 3);
+        let csv = fs::read_to_string(&files[2]).unwrap();
+        assert_eq!(csv.lines().count(), 4); // only the cells with a count
+        assert!(csv.lines().any(|l| l == "5,6,0,2,1"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
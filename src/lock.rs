@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{LockResult, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+// **********
+// LOCK STATS
+// **********
+/// What happened to a lock : how often it was taken, how long the threads waited for it
+/// and how long they kept it.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct LockStats {
+    pub acquisitions: u64,
+    /// acquisitions that had to wait for another thread to release the lock
+    pub contended: u64,
+    pub wait: Duration,
+    pub max_wait: Duration,
+    pub hold: Duration,
+    /// distinct threads that had to wait for the lock
+    pub contending_threads: usize,
+}
+
+impl LockStats {
+
+    /// Adds up the stats of several locks (the floors of a Building), the longest wait is kept.
+    /// The contending threads of different locks are counted once per lock.
+    pub fn add(&mut self, other: &LockStats) {
+        self.acquisitions += other.acquisitions;
+        self.contended += other.contended;
+        self.wait += other.wait;
+        self.max_wait = ::std::cmp::max(self.max_wait, other.max_wait);
+        self.hold += other.hold;
+        self.contending_threads += other.contending_threads;
+    }
+
+    /// The acquisitions, waits and holds since `before` (stats of the same locks, taken earlier).
+    /// The longest wait and the contending threads can not be told apart : the ones of `self` are kept.
+    pub fn minus(&self, before: &LockStats) -> LockStats {
+        LockStats {
+            acquisitions: self.acquisitions - before.acquisitions,
+            contended: self.contended - before.contended,
+            wait: self.wait - before.wait,
+            max_wait: self.max_wait,
+            hold: self.hold - before.hold,
+            contending_threads: self.contending_threads,
+        }
+    }
+}
+
+impl fmt::Display for LockStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} acquisitions ({} contended by {} threads), wait {:?} (max {:?}), hold {:?}",
+               self.acquisitions, self.contended, self.contending_threads, self.wait, self.max_wait, self.hold)
+    }
+}
+
+// ******************
+// INSTRUMENTED MUTEX
+// ******************
+/// A `Mutex` recording its `LockStats`, used in its place with the same `lock` method.
+pub struct InstrumentedMutex<T> {
+    inner: Mutex<T>,
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+    wait_nanos: AtomicU64,
+    max_wait_nanos: AtomicU64,
+    hold_nanos: AtomicU64,
+    contenders: Mutex<HashSet<ThreadId>>,
+    contending_threads: AtomicUsize,
+}
+
+/// The guard of an `InstrumentedMutex` : the lock is held (and its hold time counted) until it is dropped.
+pub struct InstrumentedGuard<'a, T: 'a> {
+    guard: MutexGuard<'a, T>,
+    lock: &'a InstrumentedMutex<T>,
+    since: Instant,
+}
+
+/// The duration as a whole number of nanoseconds.
+pub fn nanos(d: Duration) -> u64 {
+    d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64
+}
+
+impl<T> InstrumentedMutex<T> {
+
+    pub fn new(value: T) -> InstrumentedMutex<T> {
+        InstrumentedMutex {
+            inner: Mutex::new(value),
+            acquisitions: AtomicU64::new(0),
+            contended: AtomicU64::new(0),
+            wait_nanos: AtomicU64::new(0),
+            max_wait_nanos: AtomicU64::new(0),
+            hold_nanos: AtomicU64::new(0),
+            contenders: Mutex::new(HashSet::new()),
+            contending_threads: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes the lock like `Mutex::lock`, the time waited for it is recorded if it was held by another thread.
+    pub fn lock<'a>(&'a self) -> LockResult<InstrumentedGuard<'a, T>> {
+        let start = Instant::now();
+        let (result, contended) = match self.inner.try_lock() {
+            Ok(guard) => (Ok(guard), false),
+            Err(TryLockError::Poisoned(poisoned)) => (Err(poisoned), false),
+            Err(TryLockError::WouldBlock) => (self.inner.lock(), true),
+        };
+        let since = Instant::now();
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        if contended {
+            let wait = nanos(since.duration_since(start));
+            self.contended.fetch_add(1, Ordering::Relaxed);
+            self.wait_nanos.fetch_add(wait, Ordering::Relaxed);
+            self.max_wait_nanos.fetch_max(wait, Ordering::Relaxed);
+            if self.contenders.lock().unwrap().insert(thread::current().id()) {
+                self.contending_threads.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        match result {
+            Ok(guard) => Ok(InstrumentedGuard { guard, lock: self, since }),
+            Err(poisoned) => Err(PoisonError::new(InstrumentedGuard { guard: poisoned.into_inner(), lock: self, since })),
+        }
+    }
+
+    pub fn stats(&self) -> LockStats {
+        let duration = |n: &AtomicU64| {
+            let n = n.load(Ordering::Relaxed);
+            Duration::new(n / 1_000_000_000, (n % 1_000_000_000) as u32)
+        };
+        LockStats {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            wait: duration(&self.wait_nanos),
+            max_wait: duration(&self.max_wait_nanos),
+            hold: duration(&self.hold_nanos),
+            contending_threads: self.contending_threads.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Forgets the stats recorded so far (to leave the initialization out of a measure).
+    pub fn reset_stats(&self) {
+        for counter in &[&self.acquisitions, &self.contended, &self.wait_nanos, &self.max_wait_nanos, &self.hold_nanos] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.contenders.lock().unwrap().clear();
+        self.contending_threads.store(0, Ordering::Relaxed);
+    }
+}
+
+impl<'a, T> Deref for InstrumentedGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T> DerefMut for InstrumentedGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T> Drop for InstrumentedGuard<'a, T> {
+    fn drop(&mut self) {
+        // counted before the inner guard releases the lock
+        self.lock.hold_nanos.fetch_add(nanos(self.since.elapsed()), Ordering::Relaxed);
+    }
+}
@@ -6,22 +6,48 @@ extern crate env_logger;
 
 // domain objects
 use progconc::domain::*;
+use progconc::domain::config::SimConfig;
+use progconc::domain::hazard::HazardRules;
+use progconc::domain::report::{EvacuationReport, ClassReport, GroupReport, ExitReport, SteadyStateReport, OutcomeReport};
+use progconc::domain::person::Person;
+use progconc::domain::population::parse_population;
+use progconc::domain::strategy::StrategyKind;
+use progconc::domain::exit::ExitChoice;
+use progconc::domain::perception::Sign;
+use progconc::domain::arrivals::{Source, Arrivals};
+use progconc::domain::building::Building;
 
 // graphic lib wrappers
 #[cfg(feature = "gui")]
 use progconc::graphics::*;
 
 // statistics lib wrapper
-use progconc::statistics::{PerfMeasure, PerfResult};
+use progconc::statistics::{PerfMeasure, PerfResult, PerfSummary, CpuDistribution, run_in_child, thread_cpu_time};
+use progconc::export::{Exporter, RunSetup, read_aggregates, config_hash};
+use progconc::chart::bench_charts;
+use progconc::heatmap::{ImageFormat, save_heatmaps};
+use progconc::bench::BenchPlan;
 
 // arguments parsing
-use clap::{Arg, App};
+use clap::{Arg, App, SubCommand};
+
+use std::path::{Path, PathBuf};
 
 // thread and sync primitives
-use std::sync::{Mutex, Arc, Barrier};
+use std::sync::{Arc, Barrier};
 use std::thread;
-use std::io::Write;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::io::{BufWriter, Write};
 use std::io;
+use std::fs;
+use std::fs::File;
+
+// measures before and after the algorithm (if measured), outcome and final state of the Persons and of the Building
+type ScenarioResult = (Option<(PerfMeasure, PerfMeasure)>, EvacuationReport, Vec<Person>, Arc<Building>);
+
+// directory and image format of the heatmaps of a run
+type Heatmaps<'a> = Option<(&'a Path, ImageFormat)>;
 
 fn main() {
     // logger
@@ -38,6 +64,41 @@ fn main() {
         .version("0.0.1")
         .author("Antonin Perrot-Audet <antonin.perrotaudet@yahoo.com>")
         .about("test concurrent capabilities of Rust language")
+        .subcommand(SubCommand::with_name("bench")
+            .about("Measures every number of Persons with every scenario and writes the results to a single table, \
+                    the options given before bench apply to every configuration")
+            .arg(Arg::with_name("pow_pers")
+                .short("p")
+                .long("persons")
+                .takes_value(true)
+                .required(true)
+                .help("The powers of the numbers of persons, as values and ranges such as 2..16 or 2..6,8 (ends included)"))
+            .arg(Arg::with_name("scenarios")
+                .short("t")
+                .long("scenarios")
+                .takes_value(true)
+                .help("The scenarios to measure, as values and ranges (default 0,2)"))
+            .arg(Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .required(true)
+                .help("The .csv or .jsonl file of the results, an interrupted bench goes on with the configurations it misses"))
+            .arg(Arg::with_name("charts")
+                .long("charts")
+                .takes_value(true)
+                .help("A directory the SVG charts of the results are drawn to : time, user and system time and max RSS against the number of Persons"))
+            .arg(Arg::with_name("log_x")
+                .long("log-x")
+                .requires("charts")
+                .help("Draws the number of Persons on a log scale"))
+            .arg(Arg::with_name("log_y")
+                .long("log-y")
+                .requires("charts")
+                .help("Draws the measures on a log scale"))
+            .arg(Arg::with_name("error_bars")
+                .long("error-bars")
+                .requires("charts")
+                .help("Draws the standard deviation of the repetitions as error bars")))
         .arg(Arg::with_name("pow_pers")
             .short("p")
             .long("persons")
@@ -52,6 +113,143 @@ fn main() {
             .short("m")
             .long("measure")
             .help("turns on performance measurement for the selected scenario"))
+        .arg(Arg::with_name("runs")
+            .long("runs")
+            .takes_value(true)
+            .help("The number of measured simulations (default 5)"))
+        .arg(Arg::with_name("trim")
+            .long("trim")
+            .takes_value(true)
+            .help("The number of fastest and slowest measured simulations left out of the results (default 1)"))
+        .arg(Arg::with_name("percentiles")
+            .long("percentiles")
+            .takes_value(true)
+            .help("The percentiles of the wall, user and system times to report, comma separated (default 50,90)"))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .takes_value(true)
+            .help("A .csv or .jsonl file the measures and outcome of every run are written to, with an aggregate row when measured"))
+        .arg(Arg::with_name("heatmaps")
+            .long("heatmaps")
+            .takes_value(true)
+            .help("A directory the heatmaps of each floor are saved to at the end of the run (of the last one when measured) : ticks each cell was occupied and moves into it refused, as images and csv"))
+        .arg(Arg::with_name("heatmap_format")
+            .long("heatmap-format")
+            .takes_value(true)
+            .requires("heatmaps")
+            .help("The image format of the heatmaps : pgm (grey levels) or ppm (colours, default)"))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .takes_value(true)
+            .help("The seed of the random placements, hazard spreading and arrivals (default 1)"))
+        .arg(Arg::with_name("xsize")
+            .short("x")
+            .long("xsize")
+            .takes_value(true)
+            .help("The width of the Terrain (default 512)"))
+        .arg(Arg::with_name("ysize")
+            .short("y")
+            .long("ysize")
+            .takes_value(true)
+            .help("The height of the Terrain (default 128)"))
+        .arg(Arg::with_name("cost_map")
+            .long("cost-map")
+            .takes_value(true)
+            .help("A text file giving the traversal cost of each cell : one line per row (top row first), digits 1-9 are costs, other characters are normal cells"))
+        .arg(Arg::with_name("timeline")
+            .long("timeline")
+            .takes_value(true)
+            .help("A text file of layout events, one per line : '<tick> <close|open> <x1> <y1> <x2> <y2>' closes or opens a door (or barrier) covering the rectangle"))
+        .arg(Arg::with_name("exit")
+            .long("exit")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("An exit point 'x,y' added to the default exit (top left corner), can be repeated : adjacent points make a single exit"))
+        .arg(Arg::with_name("hazard")
+            .long("hazard")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("A point 'x,y' where a hazard (fire, smoke) starts, can be repeated"))
+        .arg(Arg::with_name("hazard_spread")
+            .long("hazard-spread")
+            .takes_value(true)
+            .help("The probability for a hazard cell to spread to each neighbour at each tick"))
+        .arg(Arg::with_name("hazard_aversion")
+            .long("hazard-aversion")
+            .takes_value(true)
+            .help("How much the Persons avoid cells close to a hazard, 0 (default) ignores hazards"))
+        .arg(Arg::with_name("strategy")
+            .long("strategy")
+            .takes_value(true)
+            .possible_values(&["azimuth", "exit", "random", "field", "herd"])
+            .help("The way Persons choose their moves : greedy to the azimuth (default), greedy to their chosen exit, random walk, following the distance field to the exits or herding (their exit blended with the way the crowd walks, see the panic of the population)"))
+        .arg(Arg::with_name("exit_choice")
+            .long("exit-choice")
+            .takes_value(true)
+            .possible_values(&["nearest", "least-wait", "assigned"])
+            .help("How Persons pick their exit with the 'exit' strategy : the nearest one (default), the one with the shortest estimated wait, or one assigned in turn"))
+        .arg(Arg::with_name("exit_switch_margin")
+            .long("exit-switch-margin")
+            .takes_value(true)
+            .help("The turns a Person must save to switch to a less congested exit (default 10)"))
+        .arg(Arg::with_name("population")
+            .long("population")
+            .takes_value(true)
+            .help("The population mix, e.g. 'walker:0.8,wheelchair:0.2' : classes walker (default), runner, elderly, wheelchair or custom 'name:share:speed:patience:footprint', optionally followed by ':panic' (0 to 1, how much the Persons follow the crowd with the 'herd' strategy)"))
+        .arg(Arg::with_name("memory")
+            .long("memory")
+            .takes_value(true)
+            .help("The number of recently left cells a Person avoids going back to, ties between moves being then broken at random (default 0 : no memory, ties go to the first move)"))
+        .arg(Arg::with_name("swap")
+            .long("swap")
+            .help("turns on position swapping : two face to face Persons that want each other's cell exchange them"))
+        .arg(Arg::with_name("perception")
+            .long("perception")
+            .takes_value(true)
+            .help("The distance up to which Persons see obstacles, other Persons, exits and signs (default 0 : they know the whole floor)"))
+        .arg(Arg::with_name("sign")
+            .long("sign")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("A signage cell 'x,y:x,y' showing the way to a point to the Persons with a limited perception, can be repeated"))
+        .arg(Arg::with_name("group_size")
+            .long("group-size")
+            .takes_value(true)
+            .help("The number of Persons per group (default 1 : no groups), members of a group stay together and leave together"))
+        .arg(Arg::with_name("group_distance")
+            .long("group-distance")
+            .takes_value(true)
+            .help("The maximum distance between the members of a group (default 3)"))
+        .arg(Arg::with_name("source")
+            .long("source")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .requires("duration")
+            .help("A zone 'x,y:x,y:rate[:start[:stop]]' of the ground floor where new Persons arrive, rate Persons per tick from the start tick to the stop tick, can be repeated"))
+        .arg(Arg::with_name("duration")
+            .long("duration")
+            .takes_value(true)
+            .help("The number of ticks the simulation runs for (default : until every Person left), the steady state occupancy and throughput are reported"))
+        .arg(Arg::with_name("warmup")
+            .long("warmup")
+            .takes_value(true)
+            .help("The ticks left out of the steady state averages (default : until the occupancy reaches its level of the second half of the run)"))
+        .arg(Arg::with_name("floors")
+            .long("floors")
+            .takes_value(true)
+            .help("The number of floors of the building (default 1), Persons are spread on all floors and escape by the ground floor"))
+        .arg(Arg::with_name("stair_delay")
+            .long("stair-delay")
+            .takes_value(true)
+            .help("The number of turns needed to walk down a stair (default 10)"))
+        .arg(Arg::with_name("stair_capacity")
+            .long("stair-capacity")
+            .takes_value(true)
+            .help("The number of Persons walking down a stair at the same time (default 4)"))
         .get_matches();
 
     let pow_pers: usize = matches.value_of("pow_pers").map(|n| n.parse::<usize>().unwrap())
@@ -59,147 +257,359 @@ fn main() {
     let scenario: usize = matches.value_of("scenario").map(|n| n.parse::<usize>()
         .unwrap()).unwrap_or(2_usize);
     let measure: bool = matches.is_present("measure");
+    let runs: usize = matches.value_of("runs").map(|n| n.parse::<usize>().unwrap())
+        .unwrap_or(5_usize);
+    let trim: usize = matches.value_of("trim").map(|n| n.parse::<usize>().unwrap())
+        .unwrap_or(1_usize);
+    assert!(runs > 2 * trim, "{} runs are not enough to leave out {} from each end", runs, trim);
+    let percentiles: Vec<f64> = matches.value_of("percentiles")
+        .map(|l| l.split(',').map(|p| p.trim().parse::<f64>().unwrap()).collect())
+        .unwrap_or(vec![50., 90.]);
+    assert!(percentiles.iter().all(|p| *p >= 0. && *p <= 100.), "Percentiles are between 0 and 100");
+    let mut hazard_rules = HazardRules::default();
+    if let Some(p) = matches.value_of("hazard_spread") {
+        hazard_rules.spread_probability = p.parse::<f32>().unwrap();
+    }
+    let mut config = SimConfig {
+        cost_map: matches.value_of("cost_map").map(PathBuf::from),
+        timeline: matches.value_of("timeline").map(PathBuf::from),
+        exits: matches.values_of("exit").map(|v| v.map(|p| p.parse::<Point>().unwrap()).collect())
+            .unwrap_or_default(),
+        hazards: matches.values_of("hazard").map(|v| v.map(|p| p.parse::<Point>().unwrap()).collect())
+            .unwrap_or_default(),
+        hazard_rules,
+        hazard_aversion: matches.value_of("hazard_aversion").map(|a| a.parse::<f32>().unwrap())
+            .unwrap_or(0.),
+        strategy: matches.value_of("strategy").map(|s| s.parse::<StrategyKind>().unwrap())
+            .unwrap_or_default(),
+        exit_choice: matches.value_of("exit_choice").map(|c| c.parse::<ExitChoice>().unwrap())
+            .unwrap_or_default(),
+        swap: matches.is_present("swap"),
+        signs: matches.values_of("sign").map(|v| v.map(|s| s.parse::<Sign>().unwrap()).collect())
+            .unwrap_or_default(),
+        ..SimConfig::default()
+    };
+    if let Some(seed) = matches.value_of("seed") {
+        config.seed = seed.parse::<usize>().unwrap();
+    }
+    if let Some(xsize) = matches.value_of("xsize") {
+        config.xsize = xsize.parse::<usize>().unwrap();
+    }
+    if let Some(ysize) = matches.value_of("ysize") {
+        config.ysize = ysize.parse::<usize>().unwrap();
+    }
+    if let Some(margin) = matches.value_of("exit_switch_margin") {
+        config.exit_switch_margin = margin.parse::<f32>().unwrap();
+    }
+    if let Some(population) = matches.value_of("population") {
+        config.population = parse_population(population).unwrap();
+    }
+    if let Some(perception) = matches.value_of("perception") {
+        config.perception = perception.parse::<f32>().unwrap();
+    }
+    if let Some(memory) = matches.value_of("memory") {
+        config.memory = memory.parse::<usize>().unwrap();
+    }
+    if let Some(size) = matches.value_of("group_size") {
+        config.group_size = size.parse::<usize>().unwrap();
+        assert!(config.group_size > 0, "A group has at least one member");
+    }
+    if let Some(distance) = matches.value_of("group_distance") {
+        config.group_distance = distance.parse::<f32>().unwrap();
+    }
+    if let Some(sources) = matches.values_of("source") {
+        config.sources = sources.map(|s| s.parse::<Source>().unwrap()).collect();
+    }
+    if let Some(duration) = matches.value_of("duration") {
+        config.duration = Some(duration.parse::<u64>().unwrap());
+    }
+    if let Some(warmup) = matches.value_of("warmup") {
+        config.warmup = Some(warmup.parse::<u64>().unwrap());
+    }
+    if let Some(floors) = matches.value_of("floors") {
+        config.floors = floors.parse::<usize>().unwrap();
+        assert!(config.floors > 0, "A building has at least a ground floor");
+    }
+    if let Some(delay) = matches.value_of("stair_delay") {
+        config.stair_delay = delay.parse::<u32>().unwrap();
+    }
+    if let Some(capacity) = matches.value_of("stair_capacity") {
+        config.stair_capacity = capacity.parse::<usize>().unwrap();
+        assert!(config.stair_capacity > 0, "Stairs must let at least one Person walk down");
+    }
+
+    let repetitions = Repetitions { runs, trim, percentiles };
+    let heatmap_format: ImageFormat = matches.value_of("heatmap_format").map(|f| f.parse::<ImageFormat>().unwrap())
+        .unwrap_or(ImageFormat::Ppm);
+    if let Some(dir) = matches.value_of("heatmaps") {
+        fs::create_dir_all(dir).expect("Could not create the heatmaps directory");
+    }
+    let heatmaps: Heatmaps = matches.value_of("heatmaps").map(|dir| (Path::new(dir), heatmap_format));
+
+    if let Some(bench) = matches.subcommand_matches("bench") {
+        let plan = BenchPlan::new(bench.value_of("pow_pers").unwrap(), bench.value_of("scenarios").unwrap_or("0,2")).unwrap();
+        let path = Path::new(bench.value_of("output").unwrap());
+        let config_id = repetitions.config_id(&config);
+        let (mut exporter, done) = Exporter::resume(path, config_id).expect("Could not resume the bench output file");
+        let pending = plan.pending(config.xsize, config.ysize, config.seed, config_id, &done);
+        println!("Bench of {} configurations, {} already in {}", pending.len() + done.len(), done.len(), path.display());
+        for (pow_pers, setup) in pending {
+            println!("Start simulation with \n {{ nb_pers = {} (2^{}), scenario = {}, measure = true }}", setup.persons, pow_pers, setup.scenario);
+            measure_simulation(pow_pers, setup.scenario, &repetitions, &config, Some(&mut exporter), None);
+        }
+        drop(exporter);
+        if let Some(dir) = bench.value_of("charts") {
+            let aggregates = read_aggregates(path).expect("Could not read the bench output file");
+            fs::create_dir_all(dir).expect("Could not create the charts directory");
+            for (file, chart) in bench_charts(&aggregates, bench.is_present("log_x"), bench.is_present("log_y"), bench.is_present("error_bars")) {
+                let chart_path = Path::new(dir).join(file);
+                chart.save(&chart_path).expect("Could not write a chart");
+                println!("Chart drawn to {}", chart_path.display());
+            }
+        }
+        return;
+    }
 
     let nb_pers: usize = (2_usize).pow(pow_pers as u32);
 
     println!("Start simulation with \n {{ nb_pers = {} (2^{}), scenario = {}, measure = {} }}", nb_pers, pow_pers, scenario, measure);
 
+    let mut exporter = matches.value_of("output")
+        .map(|path| Exporter::create(Path::new(path)).expect("Could not create the output file"));
+
     if measure {
-        let mut measures: Vec<PerfResult> = Vec::with_capacity(5);
-        for i in 0..5 {
-            print!("\rSimulation {}/5", i + 1);
-            io::stdout().flush().unwrap();
-
-            let measure = do_one_simulation(nb_pers, pow_pers, scenario, measure)
-                .expect("No measure returned by this simulation : something went wrong");
-            info!("Measure result : \n {}", measure);
-            measures.push(measure);
-        }
-        println!();
-        // compute mean measure and display it
-        let medians = PerfResult::take_3_median_results(measures.as_slice());
-        debug!("Removed outliers, kept :\n{:?}", medians);
-        let mean = PerfResult::compute_mean_result(&medians);
-        println!("Mean result for this simulation \n {} \n", mean);
+        measure_simulation(pow_pers, scenario, &repetitions, &config, exporter.as_mut(), heatmaps);
     } else {
-        do_one_simulation(nb_pers, pow_pers, scenario, measure);
+        let (measure, report) = do_one_simulation(nb_pers, pow_pers, scenario, measure, &config, heatmaps);
+        if let Some(ref mut exporter) = exporter {
+            let setup = RunSetup { scenario, persons: nb_pers, xsize: config.xsize, ysize: config.ysize, seed: config.seed,
+                config: repetitions.config_id(&config) };
+            exporter.write_run(&setup, 0, measure.as_ref(), &report).expect("Could not write to the output file");
+        }
+    }
+}
+
+// number of measured simulations of a configuration, and their aggregation
+struct Repetitions {
+    runs: usize,
+    trim: usize,
+    percentiles: Vec<f64>,
+}
+
+impl Repetitions {
+    // the options and repetitions the rows of an export are measured with
+    fn config_id(&self, config: &SimConfig) -> u64 {
+        config_hash(&format!("{:?} runs {} trim {}", config, self.runs, self.trim))
     }
 }
 
+// measures the repetitions of a simulation, displays their aggregation and writes them to the exporter
+fn measure_simulation(pow_pers: usize, scenario: usize, repetitions: &Repetitions, config: &SimConfig,
+                      mut exporter: Option<&mut Exporter<BufWriter<File>>>, heatmaps: Heatmaps) -> PerfSummary {
+    let nb_pers: usize = (2_usize).pow(pow_pers as u32);
+    let setup = RunSetup { scenario, persons: nb_pers, xsize: config.xsize, ysize: config.ysize, seed: config.seed,
+        config: repetitions.config_id(config) };
+    let runs = repetitions.runs;
+    let mut measures: Vec<PerfResult> = Vec::with_capacity(runs);
+    let mut reports: Vec<EvacuationReport> = Vec::with_capacity(runs);
+    for i in 0..runs {
+        print!("\rSimulation {}/{}", i + 1, runs);
+        io::stdout().flush().unwrap();
+
+        // each run in its own process : its max RSS does not include the ones of the previous runs
+        let record = run_in_child(|| {
+            let last = if i + 1 == runs { heatmaps } else { None };
+            let (measure, report) = do_one_simulation(nb_pers, pow_pers, scenario, true, config, last);
+            let measure = measure.expect("No measure returned by this simulation : something went wrong");
+            format!("{}\n{}", measure.to_record(), report.to_record())
+        }).expect("The measured simulation failed");
+        let mut records = record.lines();
+        let measure = records.next().unwrap_or("").parse::<PerfResult>()
+            .unwrap_or_else(|e| panic!("Invalid measure record of run {} of {:?} : {}", i, setup, e));
+        let report = records.next().unwrap_or("").parse::<EvacuationReport>()
+            .unwrap_or_else(|e| panic!("Invalid report record of run {} of {:?} : {}", i, setup, e));
+        info!("Measure result : \n {}", measure);
+        if let Some(ref mut exporter) = exporter {
+            exporter.write_run(&setup, i, Some(&measure), &report).expect("Could not write to the output file");
+        }
+        measures.push(measure);
+        reports.push(report);
+    }
+    println!();
+    // remove outliers, compute mean measure and spread, and display them
+    let summary = PerfSummary::new(measures.as_slice(), repetitions.trim, &repetitions.percentiles);
+    debug!("Mean of the runs kept :\n{:?}", summary.mean);
+    println!("Results for this simulation \n {} \n", summary);
+    if let Some(ref mut exporter) = exporter {
+        exporter.write_aggregate(&setup, &summary, &reports).expect("Could not write to the output file");
+    }
+    summary
+}
 
-fn do_one_simulation(nb_pers: usize, pow_pers: usize, scenario: usize, measure: bool) -> Option<PerfResult> {
+
+fn do_one_simulation(nb_pers: usize, pow_pers: usize, scenario: usize, measure: bool, config: &SimConfig,
+                     heatmaps: Heatmaps) -> (Option<PerfResult>, EvacuationReport) {
 
     // Select simulation to start according to option and compilaiton options
-    let measures: Option<(PerfMeasure, PerfMeasure)> = match (scenario, measure) {
+    let (measures, mut report, persons, building): ScenarioResult = match (scenario, measure) {
+        // the display waits for a fixed population to leave : runs with a duration are not displayed
+        (0, _) if config.duration.is_some() => t0_algorithm_perf(nb_pers, config),
+        (2, _) if config.duration.is_some() => t3_algorithm_perf(nb_pers, config),
         (0, false) => {
             // algo 0, no measure : use gui if compiled
             #[cfg(feature = "gui")]
-            let res = t0_algorithm_with_graph(nb_pers);
+            let res = t0_algorithm_with_graph(nb_pers, config);
             #[cfg(not(feature = "gui"))]
-            let res = t0_algorithm_perf(nb_pers);
+            let res = t0_algorithm_perf(nb_pers, config);
             res
         }
-        (0, true) => t0_algorithm_perf(nb_pers),
+        (0, true) => t0_algorithm_perf(nb_pers, config),
         (2, false) => {
             // algo 2, no measure : use gui if compiled
             #[cfg(feature = "gui")]
-            let res = t3_algorithm_with_graph(nb_pers);
+            let res = t3_algorithm_with_graph(nb_pers, config);
             #[cfg(not(feature = "gui"))]
-            let res = t3_algorithm_perf(nb_pers);
+            let res = t3_algorithm_perf(nb_pers, config);
             res
         }
-        (2, true) => t3_algorithm_perf(nb_pers),
+        (2, true) => t3_algorithm_perf(nb_pers, config),
         _ => unimplemented!(),
     };
 
-    // returns measurements from the simulation as a PerfResult
-    if let Some((mb, ma)) = measures {
+    // the Persons are back from their threads : their own counts are summed up
+    report.outcome = OutcomeReport::from_persons(&persons);
+    let class_reports = ClassReport::by_class(&persons, &config.population);
+    if measure {
+        info!("{}", report);
+        info!("{}", report.outcome);
+    } else {
+        println!("{}", report);
+        println!("{}", report.outcome);
+        println!("Escape curve (Persons escaped at each tick) : {:?}", report.outcome.escape_curve);
+        if class_reports.len() > 1 {
+            for class_report in &class_reports {
+                println!("{}", class_report);
+            }
+        }
+        if config.group_size > 1 {
+            println!("{}", GroupReport::from_persons(&persons));
+        }
+        if report.exit_usage.len() > 1 {
+            println!("{}", ExitReport::from_usage(&report.exit_usage));
+        }
+        if let Some(ref steady) = report.steady {
+            println!("{}", steady);
+        }
+    }
+
+    if let Some((dir, format)) = heatmaps {
+        for (floor, pterrain) in building.get_floors().iter().enumerate() {
+            let files = save_heatmaps(&pterrain.lock().unwrap(), floor, dir, format).expect("Could not write the heatmaps");
+            for file in files {
+                println!("Heatmap saved to {}", file.display());
+            }
+        }
+    }
+
+    // returns measurements from the simulation as a PerfResult, and its outcome
+    let perf = if let Some((mb, ma)) = measures {
         Some(ma.minus(&mb))
     } else {
         None
-    }
+    };
+    (perf, report)
 }
 
 
-fn t3_algorithm_perf(nb_pers: usize) -> Option<(PerfMeasure, PerfMeasure)> {
+fn t3_algorithm_perf(nb_pers: usize, config: &SimConfig) -> ScenarioResult {
     info!("Initialization");
     // ********* INITIALIZATION ********
     // Initialize the terrain and place persons in it :
     // ********* INITIALIZATION ********
-    let (terrain, mut persons) =
-        initialize_terrain_and_users(nb_pers, XSIZE, YSIZE);
+    let (building, mut persons) =
+        initialize_building_and_users(config, nb_pers, config.xsize, config.ysize);
 
-    // move Terrain to the mutex protected reference counted pointer
-    let protected_terrain = Arc::new(Mutex::new(terrain));
+    // move the Building (and its mutex protected floors) to a reference counted pointer
+    let building = Arc::new(building);
 
 
     // measure 1 (before)
     info!("Initialization done, measure starts");
+    building.reset_lock_stats(); // the initialization is left out
     let measure_before: PerfMeasure = PerfMeasure::new();
 
     // ********* ALGORITHM ********
     // start moving persons
-    'running: while protected_terrain.lock().unwrap().get_exited_cnt() < nb_pers {
+    let mut arrivals = config.duration.map(|_| Arrivals::new(config, nb_pers));
+    'running: while !is_over(&building, nb_pers, config) {
+        if let Some(ref mut arrivals) = arrivals {
+            persons.extend(arrivals.spawn(config, &building));
+            building.advance_idle_floors();
+        }
         // for each person
         for pers in persons.as_mut_slice() {
-            if !pers.has_escaped {
-                pers.look_and_move(&mut protected_terrain.lock().unwrap());
+            if !pers.has_left() {
+                building.look_and_move(pers);
             }
         }
-        debug!("****** next turn ******  {} have left the Terrain", protected_terrain.lock().unwrap().get_exited_cnt());
+        debug!("****** next turn ******  {} have left the Building", building.get_exited_cnt());
     }
 
     // measure 2
-    let measure_after: PerfMeasure = PerfMeasure::new();
+    let mut measure_after: PerfMeasure = PerfMeasure::new();
+    measure_after.lock = building.get_lock_stats();
     info!("End of algorithm, measure stops");
 
-    Some((measure_before, measure_after))
+    let mut report = EvacuationReport::from_building(&building);
+    report.steady = arrivals.map(|a| SteadyStateReport::from_building(&building, config.warmup, &a));
+    (Some((measure_before, measure_after)), report, persons, building)
 }
 
 
 #[cfg(feature = "gui")]
-fn t3_algorithm_with_graph(nb_pers: usize) -> Option<(PerfMeasure, PerfMeasure)> {
+fn t3_algorithm_with_graph(nb_pers: usize, config: &SimConfig) -> ScenarioResult {
     // ********* INITIALIZATION ********
     // Initialize the terrain and place persons in it :
     // ********* INITIALIZATION ********
-    let (terrain,
-        mut persons) = initialize_terrain_and_users(nb_pers, XSIZE, YSIZE);
+    let (building, mut persons) =
+        initialize_building_and_users(config, nb_pers, config.xsize, config.ysize);
 
-    // move Terrain to the mutex protected reference counted pointer
-    let protected_terrain = Arc::new(Mutex::new(terrain));
+    // move the Building (and its mutex protected floors) to a reference counted pointer
+    let building = Arc::new(building);
 
 
     // ********* GRAPH RELATED ********
-    let pterrain = protected_terrain.clone();
-    let graph_handle = spawn_graph_thread(pterrain, nb_pers);//, stop_graph_rx);
+    let pbuilding = building.clone();
+    let graph_handle = spawn_graph_thread(pbuilding, nb_pers);//, stop_graph_rx);
     // ********* GRAPH RELATED ********
 
     // ********* ALGORITHM ********
     // start moving persons
-    'running: while protected_terrain.lock().unwrap().get_exited_cnt() < nb_pers {
+    'running: while building.get_left_cnt() < nb_pers {
         // for each person
         for pers in persons.as_mut_slice() {
-            if !pers.has_escaped {
-                pers.look_and_move(&mut protected_terrain.lock().unwrap());
+            if !pers.has_left() {
+                building.look_and_move(pers);
             }
         }
-        debug!("****** next turn ******  {} have left the Terrain", protected_terrain.lock().unwrap().get_exited_cnt());
+        debug!("****** next turn ******  {} have left the Building", building.get_exited_cnt());
     }
 
     graph_handle.join().unwrap();
 
-    None // no measure to return
+    let report = EvacuationReport::from_building(&building);
+    (None, report, persons, building) // no measure to return
 }
 
 
 #[cfg(feature = "gui")]
-fn t0_algorithm_with_graph(nb_pers: usize) -> Option<(PerfMeasure, PerfMeasure)> {
+fn t0_algorithm_with_graph(nb_pers: usize, config: &SimConfig) -> ScenarioResult {
     // ********* INITIALIZATION ********
-    let (terrain,
-        mut persons) = initialize_terrain_and_users(nb_pers, XSIZE, YSIZE);
+    let (building, mut persons) =
+        initialize_building_and_users(config, nb_pers, config.xsize, config.ysize);
 
-    // move Terrain to the mutex protected reference counted pointer
-    let protected_terrain = Arc::new(Mutex::new(terrain));
+    // move the Building (and its mutex protected floors) to a reference counted pointer
+    let building = Arc::new(building);
 
 
     // ********* THREAD DISTRIBUTION ********
@@ -207,26 +617,8 @@ fn t0_algorithm_with_graph(nb_pers: usize) -> Option<(PerfMeasure, PerfMeasure)>
     let barrier = Arc::new(Barrier::new(nb_pers + 1));
 
     // create one thread per person
-    while let Some(mut pers) = persons.pop() {
-        let pterrain = protected_terrain.clone();
-        //let tx = tx.clone();
-        let c = barrier.clone();
-
-        // Threads declaration :
-        let handle = thread::spawn(move || {
-            debug!("waiting {}", pers.id);
-            c.wait();
-            debug!("go ! {}", pers.id);
-            while !pers.has_escaped {
-                {
-                    // whole terrain is blocked
-                    let mut my_terrain = pterrain.lock().unwrap();
-                    pers.look_and_move(&mut my_terrain);
-                } // locked mutex goes out of scope : terrain is availabe again
-                //thread::sleep(time::Duration::from_millis(100));
-            }
-            debug!("I escaped : {}", pers.id);
-        });
+    while let Some(pers) = persons.pop() {
+        let handle = spawn_person_thread(pers, &building, Some(barrier.clone()), config.duration);
         person_thread_handles.push(handle);
     };
 
@@ -234,75 +626,112 @@ fn t0_algorithm_with_graph(nb_pers: usize) -> Option<(PerfMeasure, PerfMeasure)>
     barrier.wait();
 
     // ********* GRAPH RELATED ********
-    let pterrain = protected_terrain.clone();
-    graph_loop(pterrain, nb_pers);
+    let pbuilding = building.clone();
+    graph_loop(pbuilding, nb_pers);
     // ********* GRAPH RELATED ********
 
 
     for handle in person_thread_handles {
-        handle.join().unwrap();
+        persons.push(handle.join().unwrap().0);
     };
 
 
-    None // no measure to return
+    let report = EvacuationReport::from_building(&building);
+    (None, report, persons, building) // no measure to return
 }
 
 
-fn t0_algorithm_perf(nb_pers: usize) -> Option<(PerfMeasure, PerfMeasure)> {
+fn t0_algorithm_perf(nb_pers: usize, config: &SimConfig) -> ScenarioResult {
     info!("Initialization");
     // ********* INITIALIZATION ********
-    let (terrain,
-        mut persons) = initialize_terrain_and_users(nb_pers, XSIZE, YSIZE);
+    let (building, mut persons) =
+        initialize_building_and_users(config, nb_pers, config.xsize, config.ysize);
 
-    // move Terrain to the mutex protected reference counted pointer
-    let protected_terrain = Arc::new(Mutex::new(terrain));
+    // move the Building (and its mutex protected floors) to a reference counted pointer
+    let building = Arc::new(building);
 
 
     info!("Initialization done, measure starts");
     // measure 1 (before)
-    let measure_before: PerfMeasure = PerfMeasure::new();
+    building.reset_lock_stats(); // the initialization is left out
+    let mut measure_before: PerfMeasure = PerfMeasure::new();
 
     // ********* THREAD DISTRIBUTION ********
     let mut person_thread_handles = Vec::with_capacity(nb_pers);
     let barrier = Arc::new(Barrier::new(nb_pers + 1));
 
     // create one thread per person
-    while let Some(mut pers) = persons.pop() {
-        let pterrain = protected_terrain.clone();
-        //let tx = tx.clone();
-        let c = barrier.clone();
-
-        // Threads declaration :
-        let handle = thread::spawn(move || {
-            debug!("waiting {}", pers.id);
-            c.wait();
-            debug!("go ! {}", pers.id);
-            while !pers.has_escaped {
-                {
-                    // whole terrain is blocked
-                    let mut my_terrain = pterrain.lock().unwrap();
-                    pers.look_and_move(&mut my_terrain);
-                } // locked mutex goes out of scope : terrain is availabe again
-                //thread::sleep(time::Duration::from_millis(100));
-            }
-            debug!("I escaped : {}", pers.id);
-        });
+    while let Some(pers) = persons.pop() {
+        let handle = spawn_person_thread(pers, &building, Some(barrier.clone()), config.duration);
         person_thread_handles.push(handle);
     };
 
+    measure_before.observe_threads(); // every Person thread waits at the barrier
     barrier.wait(); // wait for everybody to be placed to start moving
 
+    // new Persons get their own thread as they arrive, until the end of the run
+    let mut arrivals = config.duration.map(|_| Arrivals::new(config, nb_pers));
+    if let Some(ref mut arrivals) = arrivals {
+        while !is_over(&building, nb_pers, config) {
+            for pers in arrivals.spawn(config, &building) {
+                person_thread_handles.push(spawn_person_thread(pers, &building, None, config.duration));
+            }
+            let tick = building.get_tick();
+            if !building.advance_idle_floors() {
+                // the Persons of the ground floor play its ticks : the next arrivals are for the next one
+                building.wait_past_tick(tick, Duration::from_millis(1));
+            }
+        }
+    }
 
+    let mut thread_times = Vec::with_capacity(person_thread_handles.len());
     for handle in person_thread_handles {
-        handle.join().unwrap();
+        let (pers, cpu) = handle.join().unwrap();
+        persons.push(pers);
+        thread_times.push(cpu);
     };
 
     // measure 2
-    let measure_after: PerfMeasure = PerfMeasure::new();
+    let mut measure_after: PerfMeasure = PerfMeasure::new();
+    measure_after.lock = building.get_lock_stats();
+    measure_after.thread_cpu = CpuDistribution::new(&thread_times);
     info!("End of algorithm, measure stops");
 
 
-    Some((measure_before, measure_after))
+    let mut report = EvacuationReport::from_building(&building);
+    report.steady = arrivals.map(|a| SteadyStateReport::from_building(&building, config.warmup, &a));
+    (Some((measure_before, measure_after)), report, persons, building)
+}
+
+// a fixed population runs until every Person left, a population with arrivals for its duration
+fn is_over(building: &Building, nb_pers: usize, config: &SimConfig) -> bool {
+    match config.duration {
+        Some(duration) => building.get_tick() >= duration,
+        None => building.get_left_cnt() >= nb_pers,
+    }
+}
+
+// a thread moving a Person until it leaves the Building or the run is over (see `is_over`),
+// it starts with the other Persons placed at the beginning if a barrier is given
+// and gives the Person back with the CPU time the thread used
+fn spawn_person_thread(mut pers: Person, building: &Arc<Building>, barrier: Option<Arc<Barrier>>, duration: Option<u64>) -> JoinHandle<(Person, Duration)> {
+    let pbuilding = building.clone();
+
+    // Threads declaration :
+    thread::spawn(move || {
+        if let Some(c) = barrier {
+            debug!("waiting {}", pers.id);
+            c.wait();
+        }
+        debug!("go ! {}", pers.id);
+        while !pers.has_left() && duration.is_none_or(|d| pbuilding.get_tick() < d) {
+            // only the floor of the Person is blocked during its move
+            pbuilding.look_and_move(&mut pers);
+            //thread::sleep(time::Duration::from_millis(100));
+        }
+        debug!("I escaped : {}", pers.id);
+        (pers, thread_cpu_time())
+    })
 }
 
 
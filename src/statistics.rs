@@ -1,9 +1,18 @@
 extern crate libc;
 
+use lock::{nanos, LockStats};
+
 use std::fmt;
 use std::cmp;
 use std::time::{Duration, Instant};
 use std::mem;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::panic;
+use std::str::FromStr;
 
 #[derive(Debug, Copy, Clone)]
 pub struct PerfMeasure {
@@ -11,25 +20,46 @@ pub struct PerfMeasure {
     pub utime: Duration,
     pub stime: Duration,
     pub maxrss: i64,
+    /// voluntary and involuntary context switches
+    pub nvcsw: i64,
+    pub nivcsw: i64,
+    /// page faults served without and with I/O
+    pub minflt: i64,
+    pub majflt: i64,
+    /// highest number of threads of the process seen (0 : unknown)
+    pub threads: usize,
+    /// locks of the simulation, as of the measure (see `Building::get_lock_stats`)
+    pub lock: LockStats,
+    /// CPU time of the Person threads that ended before the measure
+    pub thread_cpu: CpuDistribution,
     pub clock_t: u64,
 }
 
 impl PerfMeasure {
     pub fn new() -> PerfMeasure {
         // measures using posix functions
-        let measure: (libc::timeval, libc::timeval, i64) = unsafe {
+        let usage: libc::rusage = unsafe {
             let mut out: libc::rusage = mem::zeroed();
             libc::getrusage(libc::RUSAGE_SELF, &mut out);
-            (out.ru_utime, out.ru_stime, out.ru_maxrss )
+            out
         };
 
         let clock_t = unsafe { clock() };
 
-        let utime : Duration = Duration::new(measure.0.tv_sec as u64, measure.0.tv_usec as u32 * 1000);
-        let stime : Duration = Duration::new(measure.1.tv_sec as u64, measure.1.tv_usec as u32 * 1000);
+        let utime : Duration = Duration::new(usage.ru_utime.tv_sec as u64, usage.ru_utime.tv_usec as u32 * 1000);
+        let stime : Duration = Duration::new(usage.ru_stime.tv_sec as u64, usage.ru_stime.tv_usec as u32 * 1000);
 
         let top : Instant = Instant::now();
-        PerfMeasure {top, utime, stime, maxrss: measure.2, clock_t}
+        PerfMeasure {top, utime, stime, maxrss: usage.ru_maxrss,
+            nvcsw: usage.ru_nvcsw, nivcsw: usage.ru_nivcsw, minflt: usage.ru_minflt, majflt: usage.ru_majflt,
+            threads: thread_count().unwrap_or(0), lock: LockStats::default(),
+            thread_cpu: CpuDistribution::default(), clock_t}
+    }
+
+    /// Counts the threads of the process again, to catch threads started after the measure
+    /// (the threads of a scenario are gone when the second measure is taken).
+    pub fn observe_threads(&mut self) {
+        self.threads = cmp::max(self.threads, thread_count().unwrap_or(0));
     }
 
     pub fn get_maxrss_as_megabytes(&self) -> f32 {
@@ -50,13 +80,76 @@ extern {
     pub fn clock() -> libc::clock_t;
 }
 
+/// CPU time (user and system) used so far by the calling thread.
+pub fn thread_cpu_time() -> Duration {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
 
-#[derive(Debug, Copy, Clone)]
+/// How the CPU time is spread over threads : a Gini coefficient of 0 means every thread used
+/// as much CPU time, it goes towards 1 when a few threads use it all (the others starve).
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct CpuDistribution {
+    /// threads measured (0 : the scenario has no threads of its own)
+    pub threads: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub max: Duration,
+    pub gini: f64,
+}
+
+impl CpuDistribution {
+    pub fn new(times: &[Duration]) -> CpuDistribution {
+        if times.is_empty() {
+            return CpuDistribution::default();
+        }
+        let mut sorted = times.to_vec();
+        sorted.sort();
+        let cnt = sorted.len();
+        let median = if cnt % 2 == 1 { sorted[cnt / 2] } else { (sorted[cnt / 2 - 1] + sorted[cnt / 2]) / 2 };
+        let secs: Vec<f64> = sorted.iter().map(|d| as_secs(*d)).collect();
+        let total: f64 = secs.iter().sum();
+        let gini = if total > 0. {
+            // sum over the sorted values of (2i - n - 1) x_i, i from 1, over n times the total
+            let weighted: f64 = secs.iter().enumerate().map(|(i, x)| (2. * (i + 1) as f64 - cnt as f64 - 1.) * x).sum();
+            weighted / (cnt as f64 * total)
+        } else { 0. };
+        CpuDistribution { threads: cnt, min: sorted[0], median, max: sorted[cnt - 1], gini }
+    }
+}
+
+impl fmt::Display for CpuDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} threads, min {:?}, median {:?}, max {:?}, gini {:.3}", self.threads, self.min, self.median, self.max, self.gini)
+    }
+}
+
+/// Number of threads of the process, read from `/proc/self/status` (None where there is no procfs).
+pub fn thread_count() -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status.lines()
+        .find(|l| l.starts_with("Threads:"))
+        .and_then(|l| l["Threads:".len()..].trim().parse::<usize>().ok())
+}
+
+
+#[derive(Debug, Copy, Clone, Default)]
 pub struct PerfResult {
     pub time: Duration,
     pub utime: Duration,
     pub stime: Duration,
+    /// high-water mark of the resident set at the end of the run : in a child of `run_in_child`,
+    /// it includes the resident set of the parent inherited at the fork
     pub maxrss: f64,
+    pub nvcsw: u64,
+    pub nivcsw: u64,
+    pub minflt: u64,
+    pub majflt: u64,
+    pub threads: usize,
+    /// waits for the locks of the simulation between the two measures
+    pub lock: LockStats,
+    pub thread_cpu: CpuDistribution,
     pub clock_t: u64,
 }
 
@@ -66,19 +159,26 @@ impl PerfResult {
             time: mes2.top.duration_since(mes1.top),
             utime: mes2.utime - mes1.utime,
             stime: mes2.stime - mes1.stime,
-            maxrss: cmp::max(mes2.maxrss, mes1.maxrss) as f64,
+            maxrss: mes2.maxrss as f64,
+            nvcsw: (mes2.nvcsw - mes1.nvcsw) as u64,
+            nivcsw: (mes2.nivcsw - mes1.nivcsw) as u64,
+            minflt: (mes2.minflt - mes1.minflt) as u64,
+            majflt: (mes2.majflt - mes1.majflt) as u64,
+            threads: cmp::max(mes2.threads, mes1.threads),
+            lock: mes2.lock.minus(&mes1.lock),
+            thread_cpu: mes2.thread_cpu,
             clock_t: (mes2.clock_t - mes1.clock_t),
         }
     }
 
 
-    pub fn take_3_median_results(measures: &[PerfResult]) -> [PerfResult; 3] {
-        let mut extract_sort = measures.iter()
-            .map(|m| { (m.time, *m) })
-            .collect::<Vec<_>>();
-        extract_sort.sort_by_key(|p| { p.0 });
+    /// Sorts the results by wall time and drops the `trim` fastest and `trim` slowest ones.
+    pub fn take_median_results(measures: &[PerfResult], trim: usize) -> Vec<PerfResult> {
+        assert!(measures.len() > 2 * trim, "{} results are not enough to trim {} from each end", measures.len(), trim);
+        let mut sorted = measures.to_vec();
+        sorted.sort_by_key(|m| { m.time });
 
-        [extract_sort[1].1, extract_sort[2].1, extract_sort[3].1]
+        sorted[trim..sorted.len() - trim].to_vec()
     }
 
     pub fn get_maxrss_as_kilobytes(&self) -> f32 {
@@ -86,34 +186,267 @@ impl PerfResult {
     }
 
 
+    /// The mean of the results, but the largest of their maxima (threads, longest lock wait, contending threads).
     pub fn compute_mean_result(perf_results: &[PerfResult]) -> PerfResult {
-        assert_eq!(perf_results.len(), 3);
+        assert!(!perf_results.is_empty(), "No result to compute a mean from");
+        let cnt = perf_results.len();
 
         let sum: PerfResult = perf_results.iter().fold(
-            PerfResult { time: Duration::from_millis(0), utime: Duration::from_millis(0), stime: Duration::from_millis(0), maxrss: 0., clock_t: 0 },
+            PerfResult::default(),
             |mut acc, mes| {
                 acc.time += mes.time;
                 acc.clock_t += mes.clock_t;
                 acc.maxrss += mes.maxrss;
                 acc.stime += mes.stime;
                 acc.utime += mes.utime;
+                acc.nvcsw += mes.nvcsw;
+                acc.nivcsw += mes.nivcsw;
+                acc.minflt += mes.minflt;
+                acc.majflt += mes.majflt;
+                acc.threads = cmp::max(acc.threads, mes.threads);
+                acc.lock.acquisitions += mes.lock.acquisitions;
+                acc.lock.contended += mes.lock.contended;
+                acc.lock.wait += mes.lock.wait;
+                acc.lock.max_wait = cmp::max(acc.lock.max_wait, mes.lock.max_wait);
+                acc.lock.hold += mes.lock.hold;
+                acc.lock.contending_threads = cmp::max(acc.lock.contending_threads, mes.lock.contending_threads);
+                acc.thread_cpu.threads += mes.thread_cpu.threads;
+                acc.thread_cpu.min += mes.thread_cpu.min;
+                acc.thread_cpu.median += mes.thread_cpu.median;
+                acc.thread_cpu.max += mes.thread_cpu.max;
+                acc.thread_cpu.gini += mes.thread_cpu.gini;
                 acc
             });
 
         PerfResult {
-            time: sum.time / 3,
-            utime: sum.utime / 3,
-            stime: sum.stime / 3,
-            maxrss: sum.maxrss as f64 / 3_f64,
-            clock_t: sum.clock_t / 3,
+            time: sum.time / cnt as u32,
+            utime: sum.utime / cnt as u32,
+            stime: sum.stime / cnt as u32,
+            maxrss: sum.maxrss / cnt as f64,
+            nvcsw: sum.nvcsw / cnt as u64,
+            nivcsw: sum.nivcsw / cnt as u64,
+            minflt: sum.minflt / cnt as u64,
+            majflt: sum.majflt / cnt as u64,
+            threads: sum.threads,
+            lock: LockStats {
+                acquisitions: sum.lock.acquisitions / cnt as u64,
+                contended: sum.lock.contended / cnt as u64,
+                wait: sum.lock.wait / cnt as u32,
+                max_wait: sum.lock.max_wait,
+                hold: sum.lock.hold / cnt as u32,
+                contending_threads: sum.lock.contending_threads,
+            },
+            thread_cpu: CpuDistribution {
+                threads: sum.thread_cpu.threads / cnt,
+                min: sum.thread_cpu.min / cnt as u32,
+                median: sum.thread_cpu.median / cnt as u32,
+                max: sum.thread_cpu.max / cnt as u32,
+                gini: sum.thread_cpu.gini / cnt as f64,
+            },
+            clock_t: sum.clock_t / cnt as u64,
+        }
+    }
+}
+
+/// Written as its values separated by spaces, in the order of the fields (durations in nanoseconds),
+/// to be sent by the child process of a run (see `run_in_child`).
+impl PerfResult {
+    pub fn to_record(&self) -> String {
+        format!("{} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {}", nanos(self.time), nanos(self.utime), nanos(self.stime), self.maxrss,
+                self.nvcsw, self.nivcsw, self.minflt, self.majflt, self.threads, self.clock_t,
+                self.lock.acquisitions, self.lock.contended, nanos(self.lock.wait), nanos(self.lock.max_wait), nanos(self.lock.hold),
+                self.lock.contending_threads, self.thread_cpu.threads, nanos(self.thread_cpu.min), nanos(self.thread_cpu.median),
+                nanos(self.thread_cpu.max), self.thread_cpu.gini)
+    }
+}
+
+impl FromStr for PerfResult {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PerfResult, String> {
+        let values: Vec<&str> = s.split_whitespace().collect();
+        if values.len() != 21 {
+            return Err(format!("\"{}\" is not a PerfResult record", s));
         }
+        let int = |idx: usize| values[idx].parse::<u64>().map_err(|e| format!("invalid value in \"{}\" : {}", s, e));
+        let duration = |idx: usize| int(idx).map(|n| Duration::new(n / 1_000_000_000, (n % 1_000_000_000) as u32));
+        Ok(PerfResult {
+            time: duration(0)?,
+            utime: duration(1)?,
+            stime: duration(2)?,
+            maxrss: values[3].parse::<f64>().map_err(|e| format!("invalid maxrss in \"{}\" : {}", s, e))?,
+            nvcsw: int(4)?,
+            nivcsw: int(5)?,
+            minflt: int(6)?,
+            majflt: int(7)?,
+            threads: int(8)? as usize,
+            clock_t: int(9)?,
+            lock: LockStats {
+                acquisitions: int(10)?,
+                contended: int(11)?,
+                wait: duration(12)?,
+                max_wait: duration(13)?,
+                hold: duration(14)?,
+                contending_threads: int(15)? as usize,
+            },
+            thread_cpu: CpuDistribution {
+                threads: int(16)? as usize,
+                min: duration(17)?,
+                median: duration(18)?,
+                max: duration(19)?,
+                gini: values[20].parse::<f64>().map_err(|e| format!("invalid gini in \"{}\" : {}", s, e))?,
+            },
+        })
     }
 }
 
+/// Runs `run` in a child process forked from this one, and returns the text it produced.
+/// The resources used by the child (its max RSS especially) do not add up with the ones
+/// of the previous runs : each run starts from the memory of the parent process, so the max RSS
+/// of runs in children of the same parent can be compared (it includes the one of the parent at the fork).
+pub fn run_in_child<F: FnOnce() -> String>(run: F) -> io::Result<String> {
+    let mut fds = [0 as libc::c_int; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if pid == 0 {
+        // child : never goes back to the code of the parent, even if the run panics
+        unsafe { libc::close(fds[0]) };
+        let code = match panic::catch_unwind(panic::AssertUnwindSafe(run)) {
+            Ok(text) => {
+                let mut pipe = unsafe { File::from_raw_fd(fds[1]) };
+                let _ = io::stdout().flush();
+                if pipe.write_all(text.as_bytes()).is_ok() { 0 } else { 2 }
+            }
+            Err(_) => 1,
+        };
+        unsafe { libc::_exit(code) };
+    }
+
+    unsafe { libc::close(fds[1]) };
+    let mut text = String::new();
+    let read = unsafe { File::from_raw_fd(fds[0]) }.read_to_string(&mut text);
+    let mut status: libc::c_int = 0;
+    if unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    read?;
+    if !libc::WIFEXITED(status) || libc::WEXITSTATUS(status) != 0 {
+        return Err(io::Error::other(format!("the child process of the run failed (status {})", status)));
+    }
+    Ok(text)
+}
+
 impl fmt::Display for PerfResult {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "PerfResult : {{ \n  time : {:?},\n  utime : {:?},\n  stime : {:?},\n  maxrss : {}kB,\n  clock_t : {} ticks }}",
-               self.time, self.utime, self.stime, self.get_maxrss_as_kilobytes(), self.clock_t)
+        write!(f, "PerfResult : {{ \n  time : {:?},\n  utime : {:?},\n  stime : {:?},\n  maxrss : {}kB,\n  context switches : {} voluntary, {} involuntary,\n  page faults : {} minor, {} major,\n  threads : {},\n  locks : {},\n  thread cpu : {},\n  clock_t : {} ticks }}",
+               self.time, self.utime, self.stime, self.get_maxrss_as_kilobytes(), self.nvcsw, self.nivcsw, self.minflt, self.majflt, self.threads, self.lock,
+               self.thread_cpu, self.clock_t)
+    }
+}
+
+
+
+fn as_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9
+}
+
+fn from_secs(secs: f64) -> Duration {
+    let secs = secs.max(0.);
+    Duration::new(secs.trunc() as u64, (secs.fract() * 1e9) as u32)
+}
+
+/// The spread of one duration over several runs.
+#[derive(Debug, Clone)]
+pub struct Spread {
+    pub mean: Duration,
+    pub stddev: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub percentiles: Vec<(f64, Duration)>,
+}
+
+impl Spread {
+
+    /// Spread of the durations, with the percentiles asked (from 0 to 100) interpolated between the
+    /// closest ranks. The standard deviation is the sample one (0 for a single run).
+    pub fn new(durations: &[Duration], percentiles: &[f64]) -> Spread {
+        assert!(!durations.is_empty(), "No duration to compute a spread from");
+        let mut secs: Vec<f64> = durations.iter().map(|d| as_secs(*d)).collect();
+        secs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let cnt = secs.len() as f64;
+        let mean = secs.iter().sum::<f64>() / cnt;
+        let variance = if secs.len() > 1 {
+            secs.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / (cnt - 1.)
+        } else { 0. };
+
+        Spread {
+            mean: from_secs(mean),
+            stddev: from_secs(variance.sqrt()),
+            min: from_secs(secs[0]),
+            max: from_secs(secs[secs.len() - 1]),
+            percentiles: percentiles.iter().map(|p| (*p, from_secs(percentile(&secs, *p)))).collect(),
+        }
+    }
+}
+
+// linear interpolation between the closest ranks of sorted values
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p.clamp(0., 100.) / 100. * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+}
+
+impl fmt::Display for Spread {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mean {:?}, stddev {:?}, min {:?}, max {:?}", self.mean, self.stddev, self.min, self.max)?;
+        for &(p, d) in &self.percentiles {
+            write!(f, ", p{} {:?}", p, d)?;
+        }
+        Ok(())
+    }
+}
+
+/// Aggregation of the results of several runs of the same simulation : the mean result of the runs
+/// kept after trimming, and the spread of their wall, user and system times.
+#[derive(Debug, Clone)]
+pub struct PerfSummary {
+    pub runs: usize,
+    pub kept: usize,
+    pub mean: PerfResult,
+    pub time: Spread,
+    pub utime: Spread,
+    pub stime: Spread,
+}
+
+impl PerfSummary {
+
+    /// Drops the `trim` fastest and slowest runs, then aggregates the others.
+    pub fn new(measures: &[PerfResult], trim: usize, percentiles: &[f64]) -> PerfSummary {
+        let kept = PerfResult::take_median_results(measures, trim);
+        let spread = |f: fn(&PerfResult) -> Duration| {
+            Spread::new(&kept.iter().map(f).collect::<Vec<_>>(), percentiles)
+        };
+        PerfSummary {
+            runs: measures.len(),
+            kept: kept.len(),
+            mean: PerfResult::compute_mean_result(&kept),
+            time: spread(|m| m.time),
+            utime: spread(|m| m.utime),
+            stime: spread(|m| m.stime),
+        }
+    }
+}
+
+impl fmt::Display for PerfSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PerfSummary : {{ \n  runs : {} ({} kept),\n  time : {},\n  utime : {},\n  stime : {},\n  maxrss : {}kB,\n  context switches : {} voluntary, {} involuntary,\n  page faults : {} minor, {} major,\n  threads : {},\n  locks : {},\n  thread cpu : {},\n  clock_t : {} ticks }}",
+               self.runs, self.kept, self.time, self.utime, self.stime, self.mean.get_maxrss_as_kilobytes(),
+               self.mean.nvcsw, self.mean.nivcsw, self.mean.minflt, self.mean.majflt, self.mean.threads, self.mean.lock, self.mean.thread_cpu, self.mean.clock_t)
     }
 }
 
@@ -121,6 +454,9 @@ impl fmt::Display for PerfResult {
 #[cfg(test)]
 mod tests {
     use statistics;
+    use std::time::Duration;
+    use std::sync::mpsc;
+    use std::thread;
 
 
     #[test]
@@ -148,4 +484,89 @@ mod tests {
         assert!(measure_2.utime.gt(&measure_1.utime));
         assert!(measure_2.get_maxrss_as_kilobytes() > measure_1.get_maxrss_as_kilobytes());
     }
+
+
+    fn result_of_millis(millis: u64) -> statistics::PerfResult {
+        let d = Duration::from_millis(millis);
+        statistics::PerfResult { time: d, utime: d, stime: d / 2, maxrss: 1024., clock_t: millis, ..Default::default() }
+    }
+
+    #[test]
+    fn summary_trims_and_spreads_any_number_of_runs() {
+        let measures: Vec<_> = [40, 10, 20, 30, 1000, 50, 0].iter().map(|m| result_of_millis(*m)).collect();
+        let summary = statistics::PerfSummary::new(&measures, 1, &[50., 90.]);
+
+        // 0 and 1000 are dropped : 10, 20, 30, 40, 50 are kept
+        assert_eq!(summary.runs, 7);
+        assert_eq!(summary.kept, 5);
+        assert_eq!(summary.mean.time, Duration::from_millis(30));
+        assert_eq!(summary.time.min, Duration::from_millis(10));
+        assert_eq!(summary.time.max, Duration::from_millis(50));
+        assert_eq!(summary.stime.max, Duration::from_millis(25));
+        // sample standard deviation of 10..50 by 10 : sqrt(250) ms
+        assert!((summary.time.stddev.subsec_nanos() as f64 * 1e-6 - 250_f64.sqrt()).abs() < 1e-3);
+        assert_eq!(summary.time.percentiles[0], (50., Duration::from_millis(30)));
+        assert_eq!(summary.time.percentiles[1], (90., Duration::from_millis(46)));
+
+        let untrimmed = statistics::PerfSummary::new(&measures[..2], 0, &[]);
+        assert_eq!(untrimmed.mean.time, Duration::from_millis(25));
+    }
+
+    #[test]
+    fn mean_result_keeps_the_longest_lock_wait() {
+        let mut short = result_of_millis(10);
+        short.lock.max_wait = Duration::from_millis(2);
+        short.lock.contending_threads = 5;
+        let mut long = result_of_millis(30);
+        long.lock.max_wait = Duration::from_millis(8);
+        long.lock.contending_threads = 3;
+
+        let mean = statistics::PerfResult::compute_mean_result(&[short, long]);
+        assert_eq!(mean.time, Duration::from_millis(20));
+        assert_eq!(mean.lock.max_wait, Duration::from_millis(8));
+        assert_eq!(mean.lock.contending_threads, 5);
+    }
+
+    #[test]
+    fn result_counts_switches_faults_and_threads() {
+        let mut before = statistics::PerfMeasure::new();
+        let (tx, rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || { rx.recv().unwrap(); });
+        before.observe_threads();
+        thread::sleep(Duration::from_millis(5)); // gives the processor up
+        let pages: Vec<u8> = vec![1; 1 << 22];
+        tx.send(()).unwrap();
+        handle.join().unwrap();
+        let after = statistics::PerfMeasure::new();
+
+        let result = after.minus(&before);
+        println!("{}", result);
+        assert_eq!(pages.iter().map(|b| *b as usize).sum::<usize>(), 1 << 22);
+        if statistics::thread_count().is_some() {
+            assert!(result.threads >= 2);
+        }
+        assert!(result.nvcsw >= 1);
+        assert!(result.minflt >= 1);
+    }
+
+    #[test]
+    fn cpu_distribution_shows_starving_threads() {
+        let ms = Duration::from_millis;
+        let even = statistics::CpuDistribution::new(&[ms(10), ms(10), ms(10), ms(10)]);
+        assert_eq!(even.median, ms(10));
+        assert!(even.gini.abs() < 1e-9);
+
+        let starving = statistics::CpuDistribution::new(&[ms(0), ms(40), ms(0), ms(0)]);
+        assert_eq!((starving.threads, starving.min, starving.median, starving.max), (4, ms(0), ms(0), ms(40)));
+        assert!((starving.gini - 0.75).abs() < 1e-9);
+
+        let spent = thread::spawn(move || {
+            let mut j: u64 = 0;
+            while statistics::thread_cpu_time() < ms(5) {
+                j = j.wrapping_add(1);
+            }
+            statistics::thread_cpu_time()
+        }).join().unwrap();
+        assert!(spent >= ms(5));
+    }
 }
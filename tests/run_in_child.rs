@@ -0,0 +1,37 @@
+// Forking a process that runs other threads may leave their locks taken in the child :
+// this test has a binary of its own, out of the threads of the other tests.
+extern crate progconc;
+
+use progconc::statistics::{PerfMeasure, PerfResult, run_in_child};
+
+fn alloc(megabytes: usize) -> String {
+    let pages: Vec<u8> = vec![1; megabytes << 20];
+    let before = PerfMeasure::new();
+    let after = PerfMeasure::new();
+    assert_eq!(pages[pages.len() - 1], 1);
+    after.minus(&before).to_record()
+}
+
+#[test]
+fn runs_in_child_do_not_share_their_max_rss() {
+    // a single test : the tests of a binary run in threads of their own
+    let first: PerfResult = run_in_child(|| alloc(64)).unwrap().parse().unwrap();
+    let second: PerfResult = run_in_child(|| alloc(64)).unwrap().parse().unwrap();
+    let parent = PerfMeasure::new();
+    assert!(first.maxrss >= (64 << 10) as f64);
+    // the memory of the children is not the one of the parent
+    assert!((parent.maxrss as f64) < first.maxrss);
+    assert!((second.maxrss - first.maxrss).abs() < first.maxrss / 4.);
+    assert_eq!(first.to_record().parse::<PerfResult>().unwrap().to_record(), first.to_record());
+
+    assert!(run_in_child(|| panic!("failed run")).is_err());
+
+    // the larger run first : the smaller one does not inherit its high-water mark
+    let large: PerfResult = run_in_child(|| alloc(96)).unwrap().parse().unwrap();
+    let small: PerfResult = run_in_child(|| alloc(32)).unwrap().parse().unwrap();
+    assert!(small.maxrss < large.maxrss);
+    // both start from the resident set of the parent : their difference is the one of their allocations
+    let difference = (large.maxrss - small.maxrss) / 1024.;
+    assert!(difference > 48. && difference < 80., "{}MB between the runs", difference);
+    assert!(small.maxrss >= (32 << 10) as f64);
+}